@@ -0,0 +1,47 @@
+use aoc_2021::day6;
+use aoc_2021::day6::PopulationSolver;
+use aoc_2021::rng::Rng;
+use std::time::Duration;
+use std::time::Instant;
+
+// Times the day6 solvers on a generated input the size of the puzzle one:
+// 300 fish with timers between 1 and 5.
+//
+//   cargo bench --bench day6
+
+const FISH : usize = 300;
+const SEED : u64 = 6;
+const RUNS : u32 = 5;
+
+fn bench<T, F: FnMut() -> T>(name: &str, mut f: F) {
+	// Warm up
+	f();
+
+	let mut best = Duration::MAX;
+	for _ in 0..RUNS {
+		let start = Instant::now();
+		std::hint::black_box(f());
+		best = std::cmp::min(best, start.elapsed());
+	}
+	println!("{:<30} {:>12.3?}", name, best);
+}
+
+fn main() {
+	let mut rng = Rng::new(SEED);
+	let population : Vec<u8> = (0..FISH).map(|_| rng.gen_range(1, 6) as u8).collect();
+	let input = population.iter().map(|t| t.to_string()).collect::<Vec<String>>().join(",");
+	println!("day6, {} fish (best of {} runs)", FISH, RUNS);
+
+	bench("generator", || day6::parser(&input));
+
+	// The unoptimized solver keeps one entry per fish, 256 days don't fit in
+	// memory
+	bench("80 days unoptimized", || day6::Unoptimized.population(&population, 80));
+	bench("80 days optimized", || day6::Optimized.population(&population, 80));
+	bench("80 days hashmap", || day6::OptimizedHashmap.population(&population, 80));
+	bench("80 days matrix", || day6::MatrixExponentiation.population(&population, 80));
+
+	bench("256 days optimized", || day6::Optimized.population(&population, 256));
+	bench("256 days hashmap", || day6::OptimizedHashmap.population(&population, 256));
+	bench("256 days matrix", || day6::MatrixExponentiation.population(&population, 256));
+}
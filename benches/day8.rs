@@ -0,0 +1,37 @@
+use aoc_2021::day8;
+use std::time::Duration;
+use std::time::Instant;
+
+// Times the day8 generator and solvers on a large generated input, with
+// four digit displays like the puzzle.
+//
+//   cargo bench --bench day8
+
+const ENTRIES : usize = 50_000;
+const SEED : u64 = 8;
+const RUNS : u32 = 5;
+
+fn bench<T, F: FnMut() -> T>(name: &str, mut f: F) {
+	// Warm up
+	f();
+
+	let mut best = Duration::MAX;
+	for _ in 0..RUNS {
+		let start = Instant::now();
+		std::hint::black_box(f());
+		best = std::cmp::min(best, start.elapsed());
+	}
+	println!("{:<30} {:>12.3?}", name, best);
+}
+
+fn main() {
+	let (input, _) = day8::generate_input(SEED, ENTRIES, 4);
+	println!("day8, {} entries (best of {} runs)", ENTRIES, RUNS);
+
+	bench("generator", || day8::parser(&input));
+
+	let parsed = day8::parser(&input);
+	bench("part1", || day8::solve_part1(&parsed));
+	bench("part2", || day8::solve_part2(&parsed));
+	bench("part2 set difference", || day8::solve_part2_set_difference(&parsed));
+}
@@ -0,0 +1,173 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Add;
+use std::ops::Mul;
+
+// Minimal arbitrary precision unsigned integer.
+// It only implements what the puzzles need: addition, multiplication and
+// printing in base 10. The number is stored as a vector of 32 bit "digits",
+// least significant first, without leading zeros (zero is an empty vector)
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BigUint {
+	digits: Vec<u32>
+}
+
+impl BigUint {
+	pub fn zero() -> Self {
+		return BigUint { digits: Vec::new() };
+	}
+
+	pub fn is_zero(&self) -> bool {
+		return self.digits.is_empty();
+	}
+
+	fn trim(mut self) -> Self {
+		while self.digits.last() == Some(&0) {
+			self.digits.pop();
+		}
+		return self;
+	}
+
+	// Long division by a small number, returns (quotient, remainder)
+	pub fn div_rem_u32(&self, divisor: u32) -> (BigUint, u32) {
+		assert!(divisor != 0, "division by zero");
+
+		let mut quotient = vec![0_u32; self.digits.len()];
+		let mut remainder = 0_u64;
+		for (idx, digit) in self.digits.iter().enumerate().rev() {
+			let current = (remainder << 32) | *digit as u64;
+			quotient[idx] = (current / divisor as u64) as u32;
+			remainder = current % divisor as u64;
+		}
+
+		return (BigUint { digits: quotient }.trim(), remainder as u32);
+	}
+
+	pub fn rem_u32(&self, divisor: u32) -> u32 {
+		return self.div_rem_u32(divisor).1;
+	}
+}
+
+impl From<u64> for BigUint {
+	fn from(value: u64) -> Self {
+		let digits = vec![value as u32, (value >> 32) as u32];
+		return BigUint { digits }.trim();
+	}
+}
+
+impl Ord for BigUint {
+	fn cmp(&self, other: &Self) -> Ordering {
+		// No leading zeros, so more digits means a bigger number
+		return self.digits.len().cmp(&other.digits.len())
+			.then_with(|| self.digits.iter().rev().cmp(other.digits.iter().rev()));
+	}
+}
+
+impl PartialOrd for BigUint {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		return Some(self.cmp(other));
+	}
+}
+
+impl Add<&BigUint> for &BigUint {
+	type Output = BigUint;
+
+	fn add(self, other: &BigUint) -> BigUint {
+		let len = std::cmp::max(self.digits.len(), other.digits.len());
+		let mut digits = Vec::with_capacity(len + 1);
+		let mut carry = 0_u64;
+
+		for idx in 0..len {
+			let a = *self.digits.get(idx).unwrap_or(&0) as u64;
+			let b = *other.digits.get(idx).unwrap_or(&0) as u64;
+			let sum = a + b + carry;
+			digits.push(sum as u32);
+			carry = sum >> 32;
+		}
+		digits.push(carry as u32);
+
+		return BigUint { digits }.trim();
+	}
+}
+
+impl Mul<&BigUint> for &BigUint {
+	type Output = BigUint;
+
+	// Schoolbook multiplication, good enough for a few hundred digits
+	fn mul(self, other: &BigUint) -> BigUint {
+		if self.is_zero() || other.is_zero() {
+			return BigUint::zero();
+		}
+
+		let mut digits = vec![0_u32; self.digits.len() + other.digits.len()];
+		for (i, a) in self.digits.iter().enumerate() {
+			let mut carry = 0_u64;
+			for (j, b) in other.digits.iter().enumerate() {
+				let current = digits[i + j] as u64 + (*a as u64) * (*b as u64) + carry;
+				digits[i + j] = current as u32;
+				carry = current >> 32;
+			}
+			digits[i + other.digits.len()] = carry as u32;
+		}
+
+		return BigUint { digits }.trim();
+	}
+}
+
+impl fmt::Display for BigUint {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.is_zero() {
+			return write!(f, "0");
+		}
+
+		// Split the number in chunks of 9 decimal digits
+		const CHUNK: u32 = 1_000_000_000;
+		let mut chunks : Vec<u32> = Vec::new();
+		let mut current = self.clone();
+		while !current.is_zero() {
+			let (quotient, remainder) = current.div_rem_u32(CHUNK);
+			chunks.push(remainder);
+			current = quotient;
+		}
+
+		write!(f, "{}", chunks.last().unwrap())?;
+		for chunk in chunks.iter().rev().skip(1) {
+			write!(f, "{:09}", chunk)?;
+		}
+		return Ok(());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_bigint_arithmetic() {
+		let a = BigUint::from(u64::MAX);
+		let b = BigUint::from(u64::MAX);
+
+		assert_eq!((&a + &b).to_string(), (u64::MAX as u128 * 2).to_string());
+		assert_eq!((&a * &b).to_string(), (u64::MAX as u128 * u64::MAX as u128).to_string());
+		assert_eq!((&(&a * &b) * &a).to_string(), "6277101735386680762814942322444851025767571854389858533375");
+		assert_eq!(BigUint::from(0).to_string(), "0");
+		assert_eq!(BigUint::from(1_000_000_000_000).to_string(), "1000000000000");
+	}
+
+	#[test]
+	fn test_bigint_ordering() {
+		let small = BigUint::from(u64::MAX);
+		let big = &small + &BigUint::from(1);
+		assert!(small < big);
+		assert!(BigUint::from(2) > BigUint::from(1));
+		assert!(BigUint::zero() < BigUint::from(1));
+		assert_eq!(BigUint::from(7).cmp(&BigUint::from(7)), Ordering::Equal);
+	}
+
+	#[test]
+	fn test_bigint_rem() {
+		let a = &BigUint::from(u64::MAX) * &BigUint::from(12345);
+		assert_eq!(a.rem_u32(1_000_000_007) as u128, (u64::MAX as u128 * 12345) % 1_000_000_007);
+	}
+}
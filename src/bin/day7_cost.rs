@@ -0,0 +1,38 @@
+use aoc_2021::day7::{align, parser, CostExpression};
+
+// Try fuel cost models for day 7 without recompiling:
+//
+//   cargo run --bin day7_cost -- input/2021/day7.txt "min(d, 10)*3"
+//
+// Prints the best positions and their cost. Add --curve to also print the
+// cost for every position as CSV.
+
+fn main() {
+	let args : Vec<String> = std::env::args().skip(1).collect();
+	if args.len() < 2 {
+		eprintln!("usage: day7_cost <input file> <cost expression> [--curve]");
+		std::process::exit(1);
+	}
+
+	let input = std::fs::read_to_string(&args[0]).unwrap_or_else(|e| {
+		eprintln!("cannot read {}: {}", args[0], e);
+		std::process::exit(1);
+	});
+	let expression = CostExpression::parse(&args[1]).unwrap_or_else(|e| {
+		eprintln!("invalid cost expression: {}", e);
+		std::process::exit(1);
+	});
+
+	let crabs = parser(input.trim());
+	let result = align(&crabs, |d| expression.eval(d));
+
+	println!("positions: {:?}", result.positions);
+	println!("cost: {}", result.cost);
+
+	if args.iter().any(|a| a == "--curve") {
+		println!("position,cost");
+		for (position, cost) in result.curve {
+			println!("{},{}", position, cost);
+		}
+	}
+}
@@ -14,123 +14,435 @@ pub fn parser(input: &str) -> Input{
 }
 
 #[aoc(day10, part1)]
-pub fn solve_part1(input: &Input) -> u32 {
-
-	let mut count_invalid_parenthesis = 0;
-	let mut count_invalid_squares = 0;
-	let mut count_invalid_brackets = 0;
-	let mut count_invalid_angles = 0;
-
-	for line in &input.lines {
-		match check_line_invalid(&line){
-			Some(c) => {
-				match c {
-					')' => count_invalid_parenthesis += 1,
-					']' => count_invalid_squares += 1,
-					'}' => count_invalid_brackets += 1,
-					'>' => count_invalid_angles += 1,
-					_ => (),
+pub fn solve_part1(input: &Input) -> u64 {
+	let language = BracketLanguage::puzzle();
+	return input.lines.iter()
+		.map(|line| match language.check_line(line) {
+			LineStatus::Corrupted { found, .. } => language.corruption_score(&found),
+			_ => 0
+		})
+		.sum();
+}
+
+#[aoc(day10, part2)]
+pub fn solve_part2(input: &Input) -> u64 {
+	let language = BracketLanguage::puzzle();
+	let mut points : Vec<u64> = input.lines.iter()
+		.filter_map(|line| match language.check_line(line) {
+			LineStatus::Incomplete { pairs, .. } => Some(language.completion_score(&pairs)),
+			_ => None
+		})
+		.collect();
+
+	points.sort();
+
+	let middle = points.len() / 2;
+	let result = points[middle];
+
+	return result;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineStatus {
+	// Every chunk is closed
+	Valid,
+	// A chunk is closed with the wrong delimiter. `expected` is the closing
+	// delimiter of the open chunk, None if there is no open chunk
+	Corrupted { pos: usize, expected: Option<String>, found: String },
+	// Some chunks are still open, `completion` closes them. `pairs` are the
+	// pairs of the open chunks (indexes in the language pairs), innermost
+	// first like in the completion
+	Incomplete { completion: String, pairs: Vec<usize> },
+	// Something that is not a delimiter
+	UnexpectedChar { pos: usize, ch: char }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BracketPair {
+	pub open: String,
+	pub close: String,
+	// Points when the closing delimiter corrupts a line
+	pub corruption_score: u64,
+	// Points of the closing delimiter in a completion
+	pub completion_score: u64
+}
+
+// The delimiters a language uses to open and close chunks, and how the
+// lines are scored. Delimiters can be longer than one char, like /* */.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BracketLanguage {
+	pub pairs: Vec<BracketPair>,
+	// Completion score = score * base + points of the next delimiter
+	pub completion_base: u64,
+	// Skip anything that is not a delimiter instead of reporting it
+	pub ignore_unknown: bool
+}
+
+// Delimiter found at some position of a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+	Open(usize),
+	Close(usize)
+}
+
+impl BracketLanguage {
+	pub fn new(pairs: Vec<BracketPair>, completion_base: u64, ignore_unknown: bool) -> Result<Self, String> {
+		if pairs.is_empty() {
+			return Err("a language needs at least one pair".to_string());
+		}
+		if completion_base < 2 {
+			return Err(format!("invalid completion base {}", completion_base));
+		}
+
+		let mut delimiters : Vec<&str> = Vec::new();
+		for pair in &pairs {
+			for delimiter in [&pair.open, &pair.close] {
+				if delimiter.is_empty() {
+					return Err("empty delimiter".to_string());
 				}
+				if delimiters.contains(&delimiter.as_str()) {
+					return Err(format!("delimiter '{}' is used twice", delimiter));
+				}
+				delimiters.push(delimiter);
 			}
-			None => ()
 		}
+
+		return Ok(BracketLanguage { pairs, completion_base, ignore_unknown });
 	}
 
-	let result = 0
-		+ count_invalid_parenthesis * 3
-		+ count_invalid_squares * 57
-		+ count_invalid_brackets * 1197
-		+ count_invalid_angles * 25137;
+	// The chunks of the submarine navigation subsystem
+	pub fn puzzle() -> Self {
+		let pair = |open: &str, close: &str, corruption_score: u64, completion_score: u64| BracketPair {
+			open: open.to_string(),
+			close: close.to_string(),
+			corruption_score,
+			completion_score
+		};
+		return BracketLanguage::new(vec![
+			pair("(", ")", 3, 1),
+			pair("[", "]", 57, 2),
+			pair("{", "}", 1197, 3),
+			pair("<", ">", 25137, 4)
+		], 5, false).unwrap();
+	}
 
-	return result;
-}
+	// One setting per line, `#` starts a comment:
+	//   pair <open> <close> <corruption score> <completion score>
+	//   base <completion base>
+	//   ignore_unknown <true|false>
+	// The base defaults to 5 and unknown chars are reported by default.
+	pub fn from_config(config: &str) -> Result<Self, String> {
+		let mut pairs = Vec::new();
+		let mut completion_base = 5;
+		let mut ignore_unknown = false;
 
-#[aoc(day10, part2)]
-pub fn solve_part2(input: &Input) -> u64 {
-	let filtered : Vec<&String> = input.lines.iter()
-		.filter( |l| check_line_invalid(&l) == None)
-		.collect();
+		for (idx, line) in config.lines().enumerate() {
+			let line = line.split('#').next().unwrap();
+			let fields : Vec<&str> = line.split_whitespace().collect();
+			let error = || format!("line {}: invalid setting '{}'", idx + 1, line.trim());
 
-	let completion_sequences : Vec<Vec<char>> = filtered.iter()
-		.map(|line| get_completion_sequence(&line) )
-		.collect();
+			match fields[..] {
+				[] => (),
+				["pair", open, close, corruption, completion] => pairs.push(BracketPair {
+					open: open.to_string(),
+					close: close.to_string(),
+					corruption_score: corruption.parse().map_err(|_| error())?,
+					completion_score: completion.parse().map_err(|_| error())?
+				}),
+				["base", base] => completion_base = base.parse().map_err(|_| error())?,
+				["ignore_unknown", value] => ignore_unknown = value.parse().map_err(|_| error())?,
+				_ => return Err(error())
+			}
+		}
 
-	fn get_points(c :&char) -> u64 {
-		return match c {
-			')' => 1,
-			']' => 2,
-			'}' => 3,
-			'>' => 4,
-			_ => 0
+		return BracketLanguage::new(pairs, completion_base, ignore_unknown);
+	}
+
+	// Longest delimiter starting at `pos`, and its length in chars
+	fn token_at(&self, line: &[char], pos: usize) -> Option<(Token, usize)> {
+		let matches = |delimiter: &str| {
+			let len = delimiter.chars().count();
+			return line.len() >= pos + len && delimiter.chars().eq(line[pos..pos + len].iter().copied());
+		};
+
+		let mut best : Option<(Token, usize)> = None;
+		for (idx, pair) in self.pairs.iter().enumerate() {
+			for (delimiter, token) in [(&pair.open, Token::Open(idx)), (&pair.close, Token::Close(idx))] {
+				let len = delimiter.chars().count();
+				if matches(delimiter) && best.as_ref().is_none_or(|(_, best_len)| len > *best_len) {
+					best = Some((token, len));
+				}
+			}
 		}
+		return best;
 	}
 
-	let mut points : Vec<u64> = completion_sequences.iter().map(|sequence| {
-		sequence.iter().fold(0_u64, |acc, c| (acc * 5) + get_points(c))
-	})
-	.collect();
+	// Single pass over the line, positions are in chars
+	pub fn check_line(&self, line: &str) -> LineStatus {
+		let chars : Vec<char> = line.chars().collect();
+		// Pairs of the open chunks
+		let mut stack : Vec<usize> = Vec::new();
+		let mut pos = 0;
+		while pos < chars.len() {
+			match self.token_at(&chars, pos) {
+				Some((Token::Open(pair), len)) => {
+					stack.push(pair);
+					pos += len;
+				},
+				Some((Token::Close(pair), len)) => {
+					let open = stack.pop();
+					if open != Some(pair) {
+						return LineStatus::Corrupted {
+							pos,
+							expected: open.map(|p| self.pairs[p].close.clone()),
+							found: self.pairs[pair].close.clone()
+						};
+					}
+					pos += len;
+				},
+				None if self.ignore_unknown => pos += 1,
+				None => return LineStatus::UnexpectedChar { pos, ch: chars[pos] }
+			}
+		}
 
-	points.sort();
+		if stack.is_empty() {
+			return LineStatus::Valid;
+		}
+		stack.reverse();
+		return LineStatus::Incomplete {
+			completion: stack.iter().map(|p| self.pairs[*p].close.as_str()).collect(),
+			pairs: stack
+		};
+	}
 
-	let middle = points.len() / 2;
-	let result = points[middle];
+	pub fn corruption_score(&self, found: &str) -> u64 {
+		return self.pairs.iter()
+			.find(|pair| pair.close == found)
+			.map_or(0, |pair| pair.corruption_score);
+	}
 
-	return result;
+	// Score of a completion closing `pairs` (as in LineStatus::Incomplete).
+	// With a big base deep completions don't fit in a u64, the score
+	// saturates at u64::MAX
+	pub fn completion_score(&self, pairs: &[usize]) -> u64 {
+		return pairs.iter().fold(0_u64, |score, pair| {
+			score.saturating_mul(self.completion_base).saturating_add(self.pairs[*pair].completion_score)
+		});
+	}
 }
 
-fn check_line_invalid(line: &String) -> Option<char> {
-	let mut stack : Vec<char> = Vec::new();
-	for c in line.chars() {
-
-		match c {
-			'{' | '[' | '(' | '<' => stack.push(c),
-			'}' | ']' | ')' | '>' => {
-				let last_element = stack.pop().unwrap();
-				match (last_element, c) {
-					('(', ')') => continue,
-					('[', ']') => continue,
-					('{', '}') => continue,
-					('<', '>') => continue,
-					_ => return Some(c),
-				}
-			},
-			_ => ()
-		}
+// Line repair
+// -----------
+// The cheapest way to make a line valid with insertions, deletions and
+// substitutions of delimiters. It is an edit distance to the language of
+// balanced lines, solved with dynamic programming over the intervals of
+// the line: best[i][j] is the cheapest repair of the tokens i..j, and the
+// first token of a repaired interval either is deleted, or opens a chunk
+// closed by a later token or by an inserted delimiter, or a chunk is
+// opened before it by an inserted delimiter.
+// O(n^3) in the number of delimiters, fine for lines of code.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepairCosts {
+	pub insert: u64,
+	pub delete: u64,
+	pub substitute: u64
+}
+
+impl Default for RepairCosts {
+	// Insertions are the cheapest, and a substitution costs more than the
+	// two insertions it could replace, so an incomplete line is repaired by
+	// appending its completion
+	fn default() -> Self {
+		return RepairCosts { insert: 1, delete: 2, substitute: 3 };
 	}
-	None
 }
 
-fn get_completion_sequence(line: &String) -> Vec<char> {
-	let mut stack : Vec<char> = Vec::new();
-	for c in line.chars() {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+	// Positions are chars of the original line, inserts go before `pos`
+	Insert { pos: usize, text: String },
+	Delete { pos: usize, text: String },
+	Substitute { pos: usize, from: String, to: String }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repair {
+	pub line: String,
+	pub edits: Vec<Edit>,
+	pub cost: u64
+}
 
-		match c {
-			'{' | '[' | '(' | '<' => stack.push(c),
-			'}' | ']' | ')' | '>' => { stack.pop(); () },
-			_ => ()
+// How the repair of an interval starts
+#[derive(Debug, Clone, Copy)]
+enum RepairChoice {
+	Empty,
+	Delete,
+	// The first token opens `pair`, closed by token `close`
+	OpenMatch { pair: usize, close: usize },
+	// The first token opens `pair`, closed by a delimiter inserted before
+	// token `close`
+	OpenInsertClose { pair: usize, close: usize },
+	// An inserted delimiter opens `pair` before the first token, closed by
+	// token `close`
+	InsertOpen { pair: usize, close: usize }
+}
+
+// A delimiter of the line (None if it is not a delimiter of the language)
+struct RepairToken {
+	pos: usize,
+	text: String,
+	token: Option<Token>
+}
+
+impl BracketLanguage {
+	pub fn repair(&self, line: &str, costs: &RepairCosts) -> Repair {
+		let chars : Vec<char> = line.chars().collect();
+		let mut tokens : Vec<RepairToken> = Vec::new();
+		let mut pos = 0;
+		while pos < chars.len() {
+			match self.token_at(&chars, pos) {
+				Some((token, len)) => {
+					tokens.push(RepairToken { pos, text: chars[pos..pos + len].iter().collect(), token: Some(token) });
+					pos += len;
+				},
+				// Kept as they are
+				None if self.ignore_unknown => pos += 1,
+				None => {
+					tokens.push(RepairToken { pos, text: chars[pos].to_string(), token: None });
+					pos += 1;
+				}
+			}
 		}
-	}
 
-	fn get_replacement(c: &char) -> Option<char> {
-		return match c {
-			'{' => Some('}'),
-			'[' => Some(']'),
-			'(' => Some(')'),
-			'<' => Some('>'),
-			_ => None
+		// Costs are (cost, edits that are not appends at the end of the line),
+		// so among the cheapest repairs the one closest to a completion wins
+		let n = tokens.len();
+		let add = |a: (u64, u64), b: (u64, u64)| (a.0 + b.0, a.1 + b.1);
+		let change = |idx: usize, token: Token| if tokens[idx].token == Some(token) { (0, 0) } else { (costs.substitute, 1) };
+		let insert = |before: usize| (costs.insert, if before == n { 0 } else { 1 });
+
+		let mut best = vec![vec![(0, 0); n + 1]; n + 1];
+		let mut choice = vec![vec![RepairChoice::Empty; n + 1]; n + 1];
+		for len in 1..=n {
+			for i in 0..=n - len {
+				let j = i + len;
+				let mut current = (u64::MAX, u64::MAX);
+				let mut current_choice = RepairChoice::Empty;
+				let mut consider = |value: (u64, u64), c: RepairChoice| {
+					if value < current {
+						current = value;
+						current_choice = c;
+					}
+				};
+
+				for pair in 0..self.pairs.len() {
+					for close in i + 1..j {
+						let value = add(add(change(i, Token::Open(pair)), change(close, Token::Close(pair))), add(best[i + 1][close], best[close + 1][j]));
+						consider(value, RepairChoice::OpenMatch { pair, close });
+					}
+					// Later closing delimiters first, appending is better on ties
+					for close in (i + 1..=j).rev() {
+						let value = add(add(change(i, Token::Open(pair)), insert(close)), add(best[i + 1][close], best[close][j]));
+						consider(value, RepairChoice::OpenInsertClose { pair, close });
+					}
+				}
+				consider(add((costs.delete, 1), best[i + 1][j]), RepairChoice::Delete);
+				for pair in 0..self.pairs.len() {
+					for close in i..j {
+						let value = add(add(insert(i), change(close, Token::Close(pair))), add(best[i][close], best[close + 1][j]));
+						consider(value, RepairChoice::InsertOpen { pair, close });
+					}
+				}
+
+				best[i][j] = current;
+				choice[i][j] = current_choice;
+			}
 		}
+
+		let mut edits = Vec::new();
+		self.repair_edits(&tokens, &choice, 0, n, chars.len(), &mut edits);
+
+		// Apply the edits, they are sorted by position
+		let mut repaired = String::new();
+		let mut cursor = 0;
+		for edit in &edits {
+			match edit {
+				Edit::Insert { pos, text } => {
+					repaired.extend(&chars[cursor..*pos]);
+					repaired += text;
+					cursor = *pos;
+				},
+				Edit::Delete { pos, text } => {
+					repaired.extend(&chars[cursor..*pos]);
+					cursor = pos + text.chars().count();
+				},
+				Edit::Substitute { pos, from, to } => {
+					repaired.extend(&chars[cursor..*pos]);
+					repaired += to;
+					cursor = pos + from.chars().count();
+				}
+			}
+		}
+		repaired.extend(&chars[cursor..]);
+
+		return Repair { line: repaired, edits, cost: best[0][n].0 };
+	}
+
+	fn delimiter(&self, token: Token) -> &str {
+		return match token {
+			Token::Open(pair) => &self.pairs[pair].open,
+			Token::Close(pair) => &self.pairs[pair].close
+		};
 	}
 
-	stack.reverse();
-	for c in stack.iter_mut() {
-		*c = get_replacement(&*c).unwrap();
+	// Edits of the repair of tokens i..j, in the order they apply
+	fn repair_edits(&self, tokens: &[RepairToken], choice: &Vec<Vec<RepairChoice>>, i: usize, j: usize, line_len: usize, edits: &mut Vec<Edit>) {
+		let pos = |idx: usize| if idx < tokens.len() { tokens[idx].pos } else { line_len };
+		let change = |idx: usize, token: Token, edits: &mut Vec<Edit>| {
+			if tokens[idx].token != Some(token) {
+				edits.push(Edit::Substitute { pos: tokens[idx].pos, from: tokens[idx].text.clone(), to: self.delimiter(token).to_string() });
+			}
+		};
+
+		match choice[i][j] {
+			RepairChoice::Empty => (),
+			RepairChoice::Delete => {
+				edits.push(Edit::Delete { pos: tokens[i].pos, text: tokens[i].text.clone() });
+				self.repair_edits(tokens, choice, i + 1, j, line_len, edits);
+			},
+			RepairChoice::OpenMatch { pair, close } => {
+				change(i, Token::Open(pair), edits);
+				self.repair_edits(tokens, choice, i + 1, close, line_len, edits);
+				change(close, Token::Close(pair), edits);
+				self.repair_edits(tokens, choice, close + 1, j, line_len, edits);
+			},
+			RepairChoice::OpenInsertClose { pair, close } => {
+				change(i, Token::Open(pair), edits);
+				self.repair_edits(tokens, choice, i + 1, close, line_len, edits);
+				edits.push(Edit::Insert { pos: pos(close), text: self.pairs[pair].close.clone() });
+				self.repair_edits(tokens, choice, close, j, line_len, edits);
+			},
+			RepairChoice::InsertOpen { pair, close } => {
+				edits.push(Edit::Insert { pos: pos(i), text: self.pairs[pair].open.clone() });
+				self.repair_edits(tokens, choice, i, close, line_len, edits);
+				change(close, Token::Close(pair), edits);
+				self.repair_edits(tokens, choice, close + 1, j, line_len, edits);
+			}
+		}
 	}
+}
 
-	return stack;
+// Checks a line of the puzzle language
+pub fn check_line(line: &str) -> LineStatus {
+	static PUZZLE: std::sync::OnceLock<BracketLanguage> = std::sync::OnceLock::new();
+	return PUZZLE.get_or_init(BracketLanguage::puzzle).check_line(line);
 }
 
+#[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::rng::Rng;
 
 	const INPUT_LITERAL : &str =
 "[({(<(())[]>[[{[]{<()<>>
@@ -158,6 +470,141 @@ mod tests {
 		assert_eq!(result, 288957);
 	}
 
+	#[test]
+	fn test_day10_check_line() {
+		assert_eq!(check_line("{([(<{}[<>[]}>{[]{[(<()>"),
+			LineStatus::Corrupted { pos: 12, expected: Some("]".to_string()), found: "}".to_string() });
+		assert_eq!(check_line("[({(<(())[]>[[{[]{<()<>>"),
+			LineStatus::Incomplete { completion: "}}]])})]".to_string(), pairs: vec![2, 2, 1, 1, 0, 2, 0, 1] });
+		assert_eq!(check_line("([]{<>})"), LineStatus::Valid);
+		assert_eq!(check_line(""), LineStatus::Valid);
+		// This used to panic
+		assert_eq!(check_line(")("), LineStatus::Corrupted { pos: 0, expected: None, found: ")".to_string() });
+		assert_eq!(check_line("(x)"), LineStatus::UnexpectedChar { pos: 1, ch: 'x' });
+	}
+
+	#[test]
+	fn test_day10_language_config() {
+		let config = "
+# comments and C style blocks
+pair « » 10 1
+pair /* */ 20 2
+pair ( ) 30 3
+base 4
+ignore_unknown true";
+		let language = BracketLanguage::from_config(config).unwrap();
+		assert_eq!(language.completion_base, 4);
+
+		assert_eq!(language.check_line("«/* a(b) */»"), LineStatus::Valid);
+		assert_eq!(language.check_line("/* «x */»"),
+			LineStatus::Corrupted { pos: 6, expected: Some("»".to_string()), found: "*/".to_string() });
+		assert_eq!(language.corruption_score("*/"), 20);
+
+		let status = language.check_line("«(/*");
+		assert_eq!(status, LineStatus::Incomplete { completion: "*/)»".to_string(), pairs: vec![1, 2, 0] });
+		// 2, then 2 * 4 + 3, then 11 * 4 + 1
+		assert_eq!(language.completion_score(&[1, 2, 0]), 45);
+	}
+
+	#[test]
+	fn test_day10_completion_score() {
+		// The completion ")))" can't be split back into delimiters, it could
+		// be "))" + ")" too
+		let language = BracketLanguage::from_config("pair ( ) 1 1\npair < )) 1 2\nbase 10").unwrap();
+		let status = language.check_line("<(");
+		assert_eq!(status, LineStatus::Incomplete { completion: ")))".to_string(), pairs: vec![0, 1] });
+		assert_eq!(language.completion_score(&[0, 1]), 12);
+
+		// 1000^7 - 1 doesn't fit in a u64
+		let language = BracketLanguage::from_config("pair ( ) 3 999\nbase 1000").unwrap();
+		assert_eq!(language.completion_score(&[0; 6]), 1000_u64.pow(6) - 1);
+		assert_eq!(language.completion_score(&[0; 7]), u64::MAX);
+		assert_eq!(language.check_line("((((((("), LineStatus::Incomplete { completion: ")".repeat(7), pairs: vec![0; 7] });
+	}
+
+	#[test]
+	fn test_day10_language_errors() {
+		assert!(BracketLanguage::from_config("").is_err());
+		assert!(BracketLanguage::from_config("pair ( ) 3").is_err());
+		assert!(BracketLanguage::from_config("pair ( ) 3 1
+pair [ ( 1 1").is_err());
+		assert!(BracketLanguage::from_config("pair ( ) 3 1
+base 1").is_err());
+		assert!(BracketLanguage::from_config("pair ( ) 3 1
+color blue").is_err());
+		assert_eq!(BracketLanguage::from_config("pair ( ) 3 1").unwrap().check_line("(x"),
+			LineStatus::UnexpectedChar { pos: 1, ch: 'x' });
+	}
+
+	#[test]
+	fn test_day10_repair() {
+		let language = BracketLanguage::puzzle();
+		let costs = RepairCosts::default();
+
+		let repair = language.repair("(]", &costs);
+		assert_eq!(repair.line, "([])");
+		assert_eq!(repair.cost, 2);
+		assert_eq!(repair.edits, vec![
+			Edit::Insert { pos: 1, text: "[".to_string() },
+			Edit::Insert { pos: 2, text: ")".to_string() }
+		]);
+
+		assert_eq!(language.repair(")(", &costs).line, "()()");
+		assert_eq!(language.repair("([]{<>})", &costs).edits, vec![]);
+
+		let repair = language.repair("(x>", &costs);
+		assert_eq!(repair.line, "(<>)");
+		assert_eq!(repair.cost, 4);
+		// Same cost as deleting the x and inserting a <, but fewer edits
+		// before the end of the line
+		assert_eq!(repair.edits, vec![
+			Edit::Substitute { pos: 1, from: "x".to_string(), to: "<".to_string() },
+			Edit::Insert { pos: 3, text: ")".to_string() }
+		]);
+
+		// When substitutions are cheap it's better to reuse the open chunks
+		let cheap = RepairCosts { insert: 1, delete: 1, substitute: 1 };
+		let repair = language.repair("((((", &cheap);
+		assert_eq!(repair.cost, 2);
+		assert_eq!(language.check_line(&repair.line), LineStatus::Valid);
+	}
+
+	#[test]
+	fn test_day10_repair_completion() {
+		// Incomplete lines are repaired by their completion, only appending
+		let language = BracketLanguage::puzzle();
+		let mut rng = Rng::new(10);
+		let mut lines : Vec<String> = INPUT_LITERAL.lines().map(|l| l.to_string()).collect();
+		for _ in 0..100 {
+			let mut line = String::new();
+			let mut open = Vec::new();
+			for _ in 0..rng.gen_range(1, 30) {
+				if open.is_empty() || rng.gen_range(0, 3) > 0 {
+					let pair = &language.pairs[rng.gen_range(0, 4) as usize];
+					line += &pair.open;
+					open.push(pair.close.clone());
+				}
+				else {
+					line += &open.pop().unwrap();
+				}
+			}
+			lines.push(line);
+		}
+
+		for line in lines {
+			if let LineStatus::Incomplete { completion, .. } = language.check_line(&line) {
+				let repair = language.repair(&line, &RepairCosts::default());
+				assert_eq!(repair.line, format!("{}{}", line, completion));
+				assert_eq!(repair.cost, completion.len() as u64);
+				let len = line.len();
+				assert!(repair.edits.iter().all(|e| matches!(e, Edit::Insert { pos, .. } if *pos == len)));
+			}
+		}
+
+		let language = BracketLanguage::from_config("pair /* */ 1 1\npair ( ) 1 1\nignore_unknown true").unwrap();
+		assert_eq!(language.repair("/* a ( b", &RepairCosts::default()).line, "/* a ( b)*/");
+	}
+
 	#[test]
 	fn tt() {
 		let v = [']', ')', ')', ')', '}', '}', '}', '}', ']', ')', '>', '>', '}', ')'];
@@ -4,10 +4,13 @@ use std::collections::HashMap;
 
 //  https://adventofcode.com/2021/day/5
 
+// Points are 3D so the same rasterizer works for vent volumes. Plain 2D
+// input is read as lying on the z=0 plane.
 #[derive(PartialEq, Eq, Hash, Debug)]
 pub struct Point {
 	x: u32,
-	y: u32
+	y: u32,
+	z: u32
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -24,90 +27,108 @@ pub struct Input {
 pub fn parser(input: &str) -> Input{
 	let mut lines : Vec<Line> = Vec::new();
 	for line in input.lines() {
-		let coords : Vec<u32> = 
-			line.split(" -> ")
-			.map(|point| point.split(","))
-			.flatten()
-			.map(|coord| u32::from_str_radix(coord, 10).unwrap())
-			.collect();
+		let mut points = line.split(" -> ").map(parse_point);
 
 		lines.push(Line { 
-			p1: Point { x: coords[0], y: coords[1] },
-			p2: Point { x: coords[2], y: coords[3] },
+			p1: points.next().unwrap(),
+			p2: points.next().unwrap(),
 		})
 	}
 	return Input { lines: lines };
 }
 
+fn parse_point(point: &str) -> Point {
+	let coords : Vec<u32> = point.split(",")
+		.map(|coord| u32::from_str_radix(coord.trim(), 10).unwrap())
+		.collect();
+
+	return match coords.len() {
+		2 => Point { x: coords[0], y: coords[1], z: 0 },
+		3 => Point { x: coords[0], y: coords[1], z: coords[2] },
+		_ => panic!("Expected 2 or 3 coordinates, found '{}'", point),
+	};
+}
+
+// Difference between the end and start of the line for each axis
+fn line_deltas(line: &Line) -> [i32; 3] {
+	return [
+		line.p2.x as i32 - line.p1.x as i32,
+		line.p2.y as i32 - line.p1.y as i32,
+		line.p2.z as i32 - line.p1.z as i32,
+	];
+}
+
+// A line only changes along one axis
+fn is_axis_aligned(line: &Line) -> bool {
+	return line_deltas(line).iter().filter(|d| **d != 0).count() <= 1;
+}
+
+// Every axis the line moves along moves the same amount of steps, so each
+// step of the line is a step of 1 in each of those axis (45 degrees in 2D,
+// the diagonal of a cube in 3D)
+fn is_diagonal(line: &Line) -> bool {
+	let deltas : Vec<i32> = line_deltas(line).iter()
+		.filter(|d| **d != 0)
+		.map(|d| i32::abs(*d))
+		.collect();
+
+	return deltas.len() > 1 && deltas.iter().all(|d| *d == deltas[0]);
+}
+
 fn compute_frequencies(lines: &Vec<&Line>) -> HashMap<Point, u32> {
 	let mut points_freq : HashMap<Point, u32> = HashMap::new();
 	for line in lines {
-		let p = Point {x: line.p1.x, y: line.p1.y};
-		points_freq.entry(p).and_modify(|f| *f +=1).or_insert(1);
-		
-		let x_steps = line.p2.x as i32 - line.p1.x as i32;
-		let x_inc:i32 = if x_steps < 0 {-1} else if x_steps > 0 {1} else {0};
-		let x_steps = i32::abs(x_steps);
-
-		let y_steps : i32 = line.p2.y as i32 - line.p1.y as i32;
-		let y_inc:i32 = if y_steps < 0 {-1} else if y_steps > 0 {1} else {0};
-		let y_steps = i32::abs(y_steps);
-
-		let steps = std::cmp::max(x_steps, y_steps);
-
-		for step in 1..=steps {
-			let x : i32 = line.p1.x as i32 + (x_inc * step);
-			let y : i32 = line.p1.y as i32 + (y_inc * step);
-			let p = Point {x: x as u32, y: y as u32,};
+		let deltas = line_deltas(line);
+		let incs = deltas.map(i32::signum);
+		let steps = deltas.iter().map(|d| i32::abs(*d)).max().unwrap();
+
+		for step in 0..=steps {
+			let x : i32 = line.p1.x as i32 + (incs[0] * step);
+			let y : i32 = line.p1.y as i32 + (incs[1] * step);
+			let z : i32 = line.p1.z as i32 + (incs[2] * step);
+			let p = Point {x: x as u32, y: y as u32, z: z as u32};
 			points_freq.entry(p).and_modify(|f| *f +=1).or_insert(1);
 		} 
 	}
 	return points_freq;
 }
 
-fn compute_points(points_freq: &HashMap<Point, u32>) -> u32 {
+// Number of points covered by at least `threshold` lines
+fn compute_points(points_freq: &HashMap<Point, u32>, threshold: u32) -> u32 {
 	let v : Vec<&Point> = points_freq.iter()
-		.filter_map(|(key, &val)| if val >= 2 { Some(key) } else { None })	
+		.filter_map(|(key, &val)| if val >= threshold { Some(key) } else { None })	
 		.collect();
 	let result = v.len() as u32;
 	return result;
 }
 #[aoc(day5, part1)]
 pub fn solve_part1(input: &Input) -> u32 {
-
-	fn filter_line(line : &Line) -> bool {
-		return line.p1.x == line.p2.x || line.p1.y == line.p2.y;
-	}
-
 	let lines : Vec<&Line> = input.lines.iter()
-		.filter(|line| filter_line(line) )
+		.filter(|line| is_axis_aligned(line) )
 		.collect();
 	
 	let points_freq: HashMap<Point, u32> = compute_frequencies(&lines);
 
-	return compute_points(&points_freq);
+	return compute_points(&points_freq, 2);
 }
 
 
 #[aoc(day5, part2)]
 pub fn solve_part2(input: &Input) -> u32 {
+	return count_overlapping_points(input, 2);
+}
 
-	fn filter_line(line : &Line) -> bool {
-		let x_coord_delta = i32::abs(line.p2.x as i32 - line.p1.x as i32);
-		let y_coord_delta = i32::abs(line.p2.y as i32 - line.p1.y as i32);
-
-		return line.p1.x == line.p2.x 
-			|| line.p1.y == line.p2.y 
-			|| x_coord_delta == y_coord_delta;
-	}
-
+// Number of points (voxels for 3D input) covered by at least `threshold`
+// axis aligned or diagonal lines. With 2D input and a threshold of 2 this
+// is the same as part 2
+pub fn count_overlapping_points(input: &Input, threshold: u32) -> u32 {
 	let lines : Vec<&Line> = input.lines.iter()
-	.filter(|line| filter_line(line) )
-	.collect();
+		.filter(|line| is_axis_aligned(line) || is_diagonal(line) )
+		.collect();
 	
 	let points_freq: HashMap<Point, u32> = compute_frequencies(&lines);
 
-	return compute_points(&points_freq);
+	return compute_points(&points_freq, threshold);
 }
 
 #[cfg(test)]
@@ -130,8 +151,15 @@ mod tests {
 	fn test_day5_parser() {
 		let input = parser(INPUT_LITERAL);
 		assert_eq!(input.lines.len(), 10);
-		assert_eq!(input.lines[0], Line { p1: Point{ x: 0, y:9 }, p2: Point { x: 5, y: 9} });
-		assert_eq!(input.lines[9], Line { p1: Point{ x: 5, y:5 }, p2: Point { x: 8, y: 2} });
+		assert_eq!(input.lines[0], Line { p1: Point{ x: 0, y:9, z: 0 }, p2: Point { x: 5, y: 9, z: 0} });
+		assert_eq!(input.lines[9], Line { p1: Point{ x: 5, y:5, z: 0 }, p2: Point { x: 8, y: 2, z: 0} });
+	}
+
+	#[test]
+	fn test_day5_parser_3d() {
+		let input = parser("1,2,3 -> 4,5,6\n0,0 -> 0,3");
+		assert_eq!(input.lines[0], Line { p1: Point{ x: 1, y:2, z: 3 }, p2: Point { x: 4, y: 5, z: 6} });
+		assert_eq!(input.lines[1], Line { p1: Point{ x: 0, y:0, z: 0 }, p2: Point { x: 0, y: 3, z: 0} });
 	}
 
 	#[test]
@@ -149,4 +177,27 @@ mod tests {
 
 		assert_eq!(result, 12);
 	}
+
+	#[test]
+	fn test_day5_3d() {
+		// Two axis aligned lines and a cube diagonal crossing at 2,2,2.
+		// The last line is not diagonal (deltas 1,2,0) so it is ignored
+		let input = parser(
+"2,2,0 -> 2,2,4
+0,2,2 -> 4,2,2
+0,0,0 -> 3,3,3
+2,2,2 -> 3,4,2");
+
+		assert_eq!(count_overlapping_points(&input, 1), 4 + 5 + 5 - 2);
+		assert_eq!(count_overlapping_points(&input, 2), 1);
+		assert_eq!(count_overlapping_points(&input, 3), 1);
+		assert_eq!(count_overlapping_points(&input, 4), 0);
+	}
+
+	#[test]
+	fn test_day5_threshold() {
+		let input = parser(INPUT_LITERAL);
+		assert_eq!(count_overlapping_points(&input, 2), 12);
+		assert_eq!(count_overlapping_points(&input, 3), 2);
+	}
 }
\ No newline at end of file
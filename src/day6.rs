@@ -1,6 +1,8 @@
 use aoc_runner_derive::aoc;
 use aoc_runner_derive::aoc_generator;
 use std::collections::HashMap;
+use crate::bigint::BigUint;
+use crate::rng::Rng;
 
 // https://adventofcode.com/2021/day/6
 
@@ -8,6 +10,134 @@ pub struct Input {
 	population: Vec<u8>
 }
 
+// Describes how the timers of a species of fish behave.
+// Timers count down one per day, and a fish with a timer of 0 spawns a new
+// fish the next day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LifecycleModel {
+	// Value the timer of a fish is reset to after spawning
+	pub reset_timer: u8,
+	// Value of the timer of a newly spawned fish
+	pub newborn_timer: u8,
+	// Extra days added to the timers of the initial population before the
+	// simulation starts, e.g. for fish that need time to settle in
+	pub initial_delay: u8,
+}
+
+impl Default for LifecycleModel {
+	// Lanternfish
+	fn default() -> Self {
+		return LifecycleModel { reset_timer: 6, newborn_timer: 8, initial_delay: 0 };
+	}
+}
+
+impl LifecycleModel {
+	// Groups the population in buckets by timer value.
+	// There is a bucket for every value a timer can take, so for lanternfish
+	// 0..=8 means 9 buckets.
+	pub fn buckets(&self, initial_population: &Vec<u8>) -> Vec<u64> {
+		let max_initial = initial_population.iter()
+			.map(|timer| *timer as usize + self.initial_delay as usize)
+			.max()
+			.unwrap_or(0);
+		let max_timer = *[max_initial, self.reset_timer as usize, self.newborn_timer as usize]
+			.iter()
+			.max()
+			.unwrap();
+
+		let mut population : Vec<u64> = vec![0; max_timer + 1];
+		for timer in initial_population {
+			population[*timer as usize + self.initial_delay as usize] += 1;
+		}
+		return population;
+	}
+
+	// Advances the bucket population a single day
+	pub fn step(&self, population: &Vec<u64>) -> Vec<u64> {
+		return self.checked_step(population).expect("population overflowed u64");
+	}
+
+	// Same as step, but returns None if any bucket would overflow
+	pub fn checked_step(&self, population: &Vec<u64>) -> Option<Vec<u64>> {
+		let mut population_tmp = vec![0_u64; population.len()];
+
+		for (idx, count) in population.iter().enumerate() {
+			if idx == 0 {
+				let reset = self.reset_timer as usize;
+				let newborn = self.newborn_timer as usize;
+				population_tmp[reset] = population_tmp[reset].checked_add(*count)?;
+				population_tmp[newborn] = population_tmp[newborn].checked_add(*count)?;
+			}
+			else {
+				population_tmp[idx-1] = population_tmp[idx-1].checked_add(*count)?;
+			}
+		}
+		return Some(population_tmp);
+	}
+
+	// Iterator over the population of every day, starting with day 0 (the
+	// initial population). It ends when the total no longer fits in a u64
+	pub fn time_series(&self, initial_population: &Vec<u8>) -> PopulationTimeSeries {
+		return PopulationTimeSeries {
+			model: *self,
+			population: Some(self.buckets(initial_population)),
+			day: 0,
+		};
+	}
+
+	// First day in which the population is bigger than `threshold`, or None
+	// if there are no fish at all.
+	// Small thresholds are found walking the time series day by day. For big
+	// ones we do an exponential + binary search over the matrix solver, as
+	// the population never decreases.
+	pub fn first_day_exceeding(&self, initial_population: &Vec<u8>, threshold: u64) -> Option<u64> {
+		if initial_population.is_empty() {
+			return None;
+		}
+
+		const LINEAR_SCAN_DAYS: usize = 128;
+		let mut scanned = 0;
+		for snapshot in self.time_series(initial_population).take(LINEAR_SCAN_DAYS) {
+			if snapshot.total > threshold {
+				return Some(snapshot.day);
+			}
+			scanned += 1;
+		}
+
+		// The series stops early when the population no longer fits in a u64,
+		// so the next day is already above any threshold
+		if scanned < LINEAR_SCAN_DAYS as u64 {
+			return Some(scanned);
+		}
+
+		// Exact counts, so we don't need to care about overflows
+		let threshold = BigUint::from(threshold);
+		let exceeds = |days: u64| self.simulate_matrix::<BigUint>(initial_population, days) > threshold;
+
+		let mut low = LINEAR_SCAN_DAYS as u64 - 1;
+		let mut high = 2 * LINEAR_SCAN_DAYS as u64;
+		while !exceeds(high) {
+			low = high;
+			high *= 2;
+		}
+
+		// Invariant: population(low) <= threshold < population(high)
+		while high - low > 1 {
+			let middle = low + (high - low) / 2;
+			if exceeds(middle) { high = middle; } else { low = middle; }
+		}
+		return Some(high);
+	}
+
+	pub fn simulate(&self, initial_population: &Vec<u8>, days: u16) -> u64 {
+		let mut population = self.buckets(initial_population);
+		for _ in 0..days {
+			population = self.step(&population);
+		}
+		return population.iter().sum();
+	}
+}
+
 
 #[aoc_generator(day6)]
 pub fn parser(input: &str) -> Input{
@@ -20,12 +150,82 @@ pub fn parser(input: &str) -> Input{
 
 #[aoc(day6, part1)]
 pub fn solve_part1(input: &Input) -> u64 {
-	return solver_optimized_hashmap(&input.population, 80);
+	return OptimizedHashmap.population(&input.population, 80);
 }
 
 #[aoc(day6, part2)]
 pub fn solve_part2(input: &Input) -> u64 {
-	return solver_optimized_hashmap(&input.population, 256);
+	return OptimizedHashmap.population(&input.population, 256);
+}
+
+// The different solvers are registered as named runner variants, so 
+// `cargo aoc -d 6` runs all of them. `cargo bench --bench day6` compares
+// their timings on a generated input.
+// The unoptimized one is only registered for part 1, part 2 does not fit in
+// memory with it.
+
+#[aoc(day6, part1, Unoptimized)]
+pub fn solve_part1_unoptimized(input: &Input) -> u64 {
+	return Unoptimized.population(&input.population, 80);
+}
+
+#[aoc(day6, part1, Optimized)]
+pub fn solve_part1_optimized(input: &Input) -> u64 {
+	return Optimized.population(&input.population, 80);
+}
+
+#[aoc(day6, part2, Optimized)]
+pub fn solve_part2_optimized(input: &Input) -> u64 {
+	return Optimized.population(&input.population, 256);
+}
+
+#[aoc(day6, part1, Matrix)]
+pub fn solve_part1_matrix(input: &Input) -> u64 {
+	return MatrixExponentiation.population(&input.population, 80);
+}
+
+#[aoc(day6, part2, Matrix)]
+pub fn solve_part2_matrix(input: &Input) -> u64 {
+	return MatrixExponentiation.population(&input.population, 256);
+}
+
+// Common interface for the lanternfish solvers
+pub trait PopulationSolver {
+	// Number of fish after `days`, given the timers of the initial population
+	fn population(&self, initial_population: &Vec<u8>, days: u16) -> u64;
+}
+
+// One vector entry per fish
+pub struct Unoptimized;
+// One bucket per timer value, stored in a vector
+pub struct Optimized;
+// One bucket per timer value, stored in a hashmap
+pub struct OptimizedHashmap;
+// Transition matrix exponentiation
+pub struct MatrixExponentiation;
+
+impl PopulationSolver for Unoptimized {
+	fn population(&self, initial_population: &Vec<u8>, days: u16) -> u64 {
+		return solver_unoptimized(initial_population, days);
+	}
+}
+
+impl PopulationSolver for Optimized {
+	fn population(&self, initial_population: &Vec<u8>, days: u16) -> u64 {
+		return solver_optimized(initial_population, days);
+	}
+}
+
+impl PopulationSolver for OptimizedHashmap {
+	fn population(&self, initial_population: &Vec<u8>, days: u16) -> u64 {
+		return solver_optimized_hashmap(initial_population, days);
+	}
+}
+
+impl PopulationSolver for MatrixExponentiation {
+	fn population(&self, initial_population: &Vec<u8>, days: u16) -> u64 {
+		return solver_matrix::<u64>(initial_population, days as u64);
+	}
 }
 
 pub fn solver_unoptimized(initial_population:&Vec<u8>, days:u16) -> u64{
@@ -50,14 +250,6 @@ pub fn solver_unoptimized(initial_population:&Vec<u8>, days:u16) -> u64{
 	// and also takes probably more memory than your system has
 	// (1billion bytes == 1GB)
 
-	// Bench data:
-	// AOC 2021
-	// Day 6 - Part 1 : 360268
-	// generator: 20.3µs,
-	// runner: 13.8707ms
-	//
-	// Day 6 - Part 2 : N/A
-
 	let mut population = initial_population.clone();
 	for _ in 0..days {
 		let mut population_tmp : Vec<u8> = Vec::new();
@@ -99,57 +291,14 @@ pub fn solver_optimized(initial_population:&Vec<u8>, days: u16) -> u64 {
 	// I've changed the solution to use a vector instead of a hashmap, gaining
 	// some performance.
 
-	// Bench data:
-	// AOC 2021
-	// Day 6 - Part 1 : 360268
-	// 		generator: 34.6µs,
-	// 		runner: 10.2µs
-	
-	// Day 6 - Part 2 : 1632146183902
-	// 		generator: 15.3µs,
-	// 		runner: 25.9µs
-	
-	let mut population : Vec<u64> = vec![0;9];
-	
-	for count in initial_population {
-		population[*count as usize] += 1;
-	}
-
-	for _ in 0..days {
-		
-		// let mut population_tmp : HashMap<u8, u64> = HashMap::new();
-		// for (idx, count) in &population  { population_tmp.insert(*idx, 0); }
-		let mut population_tmp = vec![0;9];
-
-		for (idx, count) in population.iter().enumerate() {
-			if idx == 0 {
-				population_tmp[6] += count;
-				population_tmp[8] += count;
-			}
-			else {
-				population_tmp[idx-1] += count;
-			} 
-		}
-
-		population = population_tmp;
-	}
-
-	return population.iter().fold(0, |acc, v| acc + v) as u64;
+	// The bucket simulation now lives in LifecycleModel so it can be used
+	// with other species, lanternfish are the default model.
+	return LifecycleModel::default().simulate(initial_population, days);
 }
 	
 pub fn solver_optimized_hashmap(initial_population:&Vec<u8>, days: u16) -> u64 {
 
 	// same as solver_optimized but using a hashmap instead of a vector
-
-	// Bench data:
-	// AOC 2021
-	// Day 6 - Part 1 : 360268
-	// 		generator: 42.9µs,
-	// 		runner: 188.8µs
-	
-	// Day 6 - Part 2 : 1632146183902
-	// 		generator: 13.4µs,
-	// 		runner: 240.7µs
 	let mut population : HashMap<u8, u64> =  HashMap::new();
 	
 	for count in initial_population {
@@ -177,6 +326,518 @@ pub fn solver_optimized_hashmap(initial_population:&Vec<u8>, days: u16) -> u64 {
 	return population.values().fold(0, |acc, v| acc + v) as u64;
 }
 
+// Population in a given day
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PopulationSnapshot {
+	pub day: u64,
+	// Number of fish for each timer value
+	pub buckets: Vec<u64>,
+	pub total: u64,
+}
+
+pub struct PopulationTimeSeries {
+	model: LifecycleModel,
+	// None once the population overflowed
+	population: Option<Vec<u64>>,
+	day: u64,
+}
+
+impl Iterator for PopulationTimeSeries {
+	type Item = PopulationSnapshot;
+
+	fn next(&mut self) -> Option<PopulationSnapshot> {
+		let buckets = self.population.take()?;
+		let total = buckets.iter().try_fold(0_u64, |acc, count| acc.checked_add(*count))?;
+
+		let snapshot = PopulationSnapshot { day: self.day, buckets, total };
+		self.population = self.model.checked_step(&snapshot.buckets);
+		self.day += 1;
+		return Some(snapshot);
+	}
+}
+
+// One row per day: day,timer_0,...,timer_8,total
+pub fn time_series_to_csv(snapshots: &[PopulationSnapshot]) -> String {
+	let buckets = snapshots.iter().map(|s| s.buckets.len()).max().unwrap_or(0);
+
+	let mut csv = String::from("day");
+	for timer in 0..buckets {
+		csv.push_str(&format!(",timer_{}", timer));
+	}
+	csv.push_str(",total\n");
+
+	for snapshot in snapshots {
+		csv.push_str(&snapshot.day.to_string());
+		for count in &snapshot.buckets {
+			csv.push_str(&format!(",{}", count));
+		}
+		csv.push_str(&format!(",{}\n", snapshot.total));
+	}
+	return csv;
+}
+
+// Array of {"day": .., "buckets": [..], "total": ..} objects
+pub fn time_series_to_json(snapshots: &[PopulationSnapshot]) -> String {
+	let entries : Vec<String> = snapshots.iter()
+		.map(|snapshot| {
+			let buckets : Vec<String> = snapshot.buckets.iter().map(|c| c.to_string()).collect();
+			format!("{{\"day\":{},\"buckets\":[{}],\"total\":{}}}", snapshot.day, buckets.join(","), snapshot.total)
+		})
+		.collect();
+
+	return format!("[{}]", entries.join(","));
+}
+
+// Numeric types the matrix solver can count fish with.
+// u64 overflows after ~440 days, so the solver is generic over the number
+// type: u128 goes a bit further, BigUint has no limit and ModP answers
+// "how many fish modulo P" for any number of days.
+pub trait PopulationCount: Clone {
+	fn zero() -> Self;
+	fn one() -> Self;
+	fn from_u64(value: u64) -> Self;
+	fn add(&self, other: &Self) -> Self;
+	fn mul(&self, other: &Self) -> Self;
+}
+
+impl PopulationCount for u64 {
+	fn zero() -> Self { 0 }
+	fn one() -> Self { 1 }
+	fn from_u64(value: u64) -> Self { value }
+	fn add(&self, other: &Self) -> Self { self + other }
+	fn mul(&self, other: &Self) -> Self { self * other }
+}
+
+impl PopulationCount for u128 {
+	fn zero() -> Self { 0 }
+	fn one() -> Self { 1 }
+	fn from_u64(value: u64) -> Self { value as u128 }
+	fn add(&self, other: &Self) -> Self { self + other }
+	fn mul(&self, other: &Self) -> Self { self * other }
+}
+
+impl PopulationCount for BigUint {
+	fn zero() -> Self { BigUint::zero() }
+	fn one() -> Self { BigUint::from(1) }
+	fn from_u64(value: u64) -> Self { BigUint::from(value) }
+	fn add(&self, other: &Self) -> Self { self + other }
+	fn mul(&self, other: &Self) -> Self { self * other }
+}
+
+// Integer modulo P, e.g. ModP<1_000_000_007>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModP<const P: u64>(pub u64);
+
+impl<const P: u64> PopulationCount for ModP<P> {
+	fn zero() -> Self { ModP(0) }
+	fn one() -> Self { ModP(1 % P) }
+	fn from_u64(value: u64) -> Self { ModP(value % P) }
+	fn add(&self, other: &Self) -> Self { ModP(((self.0 as u128 + other.0 as u128) % P as u128) as u64) }
+	fn mul(&self, other: &Self) -> Self { ModP(((self.0 as u128 * other.0 as u128) % P as u128) as u64) }
+}
+
+type Matrix<T> = Vec<Vec<T>>;
+
+fn matrix_identity<T: PopulationCount>(size: usize) -> Matrix<T> {
+	let mut result = vec![vec![T::zero(); size]; size];
+	for (idx, row) in result.iter_mut().enumerate() {
+		row[idx] = T::one();
+	}
+	return result;
+}
+
+fn matrix_mul<T: PopulationCount>(a: &Matrix<T>, b: &Matrix<T>) -> Matrix<T> {
+	let size = a.len();
+	let mut result = vec![vec![T::zero(); size]; size];
+	for row in 0..size {
+		for k in 0..size {
+			for col in 0..size {
+				result[row][col] = result[row][col].add(&a[row][k].mul(&b[k][col]));
+			}
+		}
+	}
+	return result;
+}
+
+impl LifecycleModel {
+	// Matrix M so that tomorrow = M * today, where today and tomorrow are
+	// the bucket vectors. M[i][j] is how many fish in bucket i a single fish
+	// in bucket j turns into after one day.
+	pub fn transition_matrix<T: PopulationCount>(&self, size: usize) -> Matrix<T> {
+		let mut matrix = vec![vec![T::zero(); size]; size];
+		for idx in 1..size {
+			matrix[idx - 1][idx] = T::one();
+		}
+		let reset = self.reset_timer as usize;
+		let newborn = self.newborn_timer as usize;
+		matrix[reset][0] = matrix[reset][0].add(&T::one());
+		matrix[newborn][0] = matrix[newborn][0].add(&T::one());
+		return matrix;
+	}
+
+	// Same result as simulate, but raising the transition matrix to the
+	// power of `days` by repeated squaring, so it takes O(log days) matrix
+	// multiplications instead of one step per day.
+	pub fn simulate_matrix<T: PopulationCount>(&self, initial_population: &Vec<u8>, days: u64) -> T {
+		let buckets = self.buckets(initial_population);
+		let size = buckets.len();
+
+		let mut power = self.transition_matrix::<T>(size);
+		let mut result = matrix_identity::<T>(size);
+		let mut exponent = days;
+		while exponent > 0 {
+			if exponent & 1 == 1 {
+				result = matrix_mul(&result, &power);
+			}
+			exponent >>= 1;
+			// Skip the last squaring, it is not used and could overflow
+			if exponent > 0 {
+				power = matrix_mul(&power, &power);
+			}
+		}
+
+		// Total = sum over rows of (M^days * buckets)
+		let mut total = T::zero();
+		for row in &result {
+			for (col, count) in buckets.iter().enumerate() {
+				if *count == 0 { continue; }
+				total = total.add(&row[col].mul(&T::from_u64(*count)));
+			}
+		}
+		return total;
+	}
+}
+
+pub fn solver_matrix<T: PopulationCount>(initial_population: &Vec<u8>, days: u64) -> T {
+	return LifecycleModel::default().simulate_matrix(initial_population, days);
+}
+
+// Stochastic simulation
+// ---------------------
+// The deterministic model moves whole buckets around. Here every day the
+// fish of each bucket can die with some probability, and the fish that
+// spawn get their new timers (and the newborns timers) drawn from a
+// distribution. Counts are still kept per bucket, so instead of rolling
+// the dice for every fish we sample how many fish of a bucket end up in
+// each place (binomial/multinomial sampling).
+
+// Distribution of the value a timer is set to
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimerDistribution {
+	Fixed(u8),
+	// Any value in low..=high with the same probability
+	Uniform { low: u8, high: u8 },
+	// (timer, weight) pairs, weights don't need to add up to 1
+	Weighted(Vec<(u8, f64)>),
+}
+
+impl TimerDistribution {
+	fn max_timer(&self) -> u8 {
+		return match self {
+			TimerDistribution::Fixed(timer) => *timer,
+			TimerDistribution::Uniform { high, .. } => *high,
+			TimerDistribution::Weighted(weights) => weights.iter().map(|(t, _)| *t).max().unwrap_or(0),
+		};
+	}
+
+	// A distribution needs at least one timer with a positive weight, and
+	// no negative or infinite weights
+	pub fn validate(&self) -> Result<(), String> {
+		match self {
+			TimerDistribution::Fixed(_) => (),
+			TimerDistribution::Uniform { low, high } => {
+				if low > high {
+					return Err(format!("empty timer range {}..={}", low, high));
+				}
+			},
+			TimerDistribution::Weighted(weights) => {
+				if let Some((timer, weight)) = weights.iter().find(|(_, w)| !w.is_finite() || *w < 0.0) {
+					return Err(format!("invalid weight {} for timer {}", weight, timer));
+				}
+				if !weights.iter().any(|(_, w)| *w > 0.0) {
+					return Err("timer weights must not be all zero".to_string());
+				}
+			}
+		}
+		return Ok(());
+	}
+
+	// Timers that can happen, with their weights
+	fn weights(&self) -> Vec<(u8, f64)> {
+		return match self {
+			TimerDistribution::Fixed(timer) => vec![(*timer, 1.0)],
+			TimerDistribution::Uniform { low, high } => (*low..=*high).map(|t| (t, 1.0)).collect(),
+			TimerDistribution::Weighted(weights) => weights.iter().filter(|(_, w)| *w > 0.0).copied().collect(),
+		};
+	}
+
+	// Splits `count` fish across the timers of the distribution.
+	// Returns (timer, number of fish) pairs
+	fn split(&self, count: u64, rng: &mut Rng) -> Vec<(u8, u64)> {
+		let weights = self.weights();
+		let mut remaining_weight : f64 = weights.iter().map(|(_, w)| w).sum();
+		let mut remaining = count;
+		let mut result = Vec::new();
+
+		// Multinomial sampling as a sequence of binomials
+		for (idx, (timer, weight)) in weights.iter().enumerate() {
+			let amount = if idx == weights.len() - 1 {
+				remaining
+			}
+			else {
+				sample_binomial(remaining, weight / remaining_weight, rng)
+			};
+			result.push((*timer, amount));
+			remaining -= amount;
+			remaining_weight -= weight;
+		}
+		return result;
+	}
+}
+
+// Number of successes in `n` trials with probability `p`.
+// Exact for small n, normal approximation for big n. When p is 0 or 1 no
+// random number is used at all, so a simulation without randomness is
+// fully deterministic.
+fn sample_binomial(n: u64, p: f64, rng: &mut Rng) -> u64 {
+	if n == 0 || p <= 0.0 { return 0; }
+	if p >= 1.0 { return n; }
+
+	if n < 64 {
+		return (0..n).filter(|_| rng.gen_f64() < p).count() as u64;
+	}
+
+	// The normal approximation is only good when the variance is big enough,
+	// otherwise the draws pile up around 0 (or n)
+	let variance = n as f64 * p * (1.0 - p);
+	if variance <= 9.0 {
+		return sample_binomial_inversion(n, p, rng);
+	}
+
+	// Box-Muller transform to get a standard normal value
+	let u1 = 1.0 - rng.gen_f64();
+	let u2 = rng.gen_f64();
+	let normal = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+
+	let mean = n as f64 * p;
+	let deviation = (n as f64 * p * (1.0 - p)).sqrt();
+	return (mean + normal * deviation).round().clamp(0.0, n as f64) as u64;
+}
+
+// Exact sampling walking the cumulative distribution from 0. With a small
+// variance n * min(p, 1 - p) is small too, so it only takes a few steps
+fn sample_binomial_inversion(n: u64, p: f64, rng: &mut Rng) -> u64 {
+	if p > 0.5 {
+		return n - sample_binomial_inversion(n, 1.0 - p, rng);
+	}
+
+	let u = rng.gen_f64();
+	let ratio = p / (1.0 - p);
+	let mut k = 0;
+	let mut probability = (1.0 - p).powf(n as f64);
+	let mut cumulative = probability;
+	while u >= cumulative && k < n {
+		probability *= (n - k) as f64 / (k + 1) as f64 * ratio;
+		k += 1;
+		cumulative += probability;
+	}
+	return k;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeciesModel {
+	// Timer of a fish after spawning
+	pub reset: TimerDistribution,
+	// Timer of a newborn fish
+	pub newborn: TimerDistribution,
+	// Probability of every fish dying each day
+	pub death_probability: f64,
+}
+
+impl SpeciesModel {
+	pub fn new(reset: TimerDistribution, newborn: TimerDistribution, death_probability: f64) -> Result<Self, String> {
+		let model = SpeciesModel { reset, newborn, death_probability };
+		model.validate()?;
+		return Ok(model);
+	}
+
+	pub fn validate(&self) -> Result<(), String> {
+		self.reset.validate().map_err(|e| format!("reset timer: {}", e))?;
+		self.newborn.validate().map_err(|e| format!("newborn timer: {}", e))?;
+		if !(0.0..=1.0).contains(&self.death_probability) {
+			return Err(format!("invalid death probability {}", self.death_probability));
+		}
+		return Ok(());
+	}
+}
+
+impl From<LifecycleModel> for SpeciesModel {
+	// Deterministic species, initial_delay is not part of the species but
+	// of how the initial population is set up, so it is ignored here
+	fn from(model: LifecycleModel) -> Self {
+		return SpeciesModel {
+			reset: TimerDistribution::Fixed(model.reset_timer),
+			newborn: TimerDistribution::Fixed(model.newborn_timer),
+			death_probability: 0.0,
+		};
+	}
+}
+
+// Fish of species `by` increase the death probability of species
+// `affected`, proportionally to the share of the total population they are.
+// With `rate` 0.1 and a population that is all `by` fish, `affected` fish
+// have an extra 10% chance of dying each day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interaction {
+	pub affected: usize,
+	pub by: usize,
+	pub rate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StochasticSimulation {
+	pub species: Vec<SpeciesModel>,
+	pub interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PopulationStats {
+	pub mean: f64,
+	pub variance: f64,
+	// (percentile, population) pairs, e.g. (0.95, 1234)
+	pub percentiles: Vec<(f64, u64)>,
+}
+
+impl PopulationStats {
+	fn from_samples(samples: &[u64], percentiles: &[f64]) -> Self {
+		let len = samples.len() as f64;
+		let mean = samples.iter().map(|v| *v as f64).sum::<f64>() / len;
+		let variance = samples.iter().map(|v| (*v as f64 - mean).powi(2)).sum::<f64>() / len;
+
+		let mut sorted = samples.to_vec();
+		sorted.sort();
+		// Nearest rank method
+		let percentiles = percentiles.iter()
+			.map(|p| {
+				let rank = (p * len).ceil() as usize;
+				(*p, sorted[rank.clamp(1, sorted.len()) - 1])
+			})
+			.collect();
+
+		return PopulationStats { mean, variance, percentiles };
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationReport {
+	// One entry per species
+	pub species: Vec<PopulationStats>,
+	pub total: PopulationStats,
+}
+
+impl StochasticSimulation {
+	// Simulation with the given species and no interactions
+	pub fn new(species: Vec<SpeciesModel>) -> Result<Self, String> {
+		let simulation = StochasticSimulation { species, interactions: Vec::new() };
+		simulation.validate()?;
+		return Ok(simulation);
+	}
+
+	pub fn validate(&self) -> Result<(), String> {
+		for (idx, species) in self.species.iter().enumerate() {
+			species.validate().map_err(|e| format!("species {}: {}", idx, e))?;
+		}
+		for interaction in &self.interactions {
+			if interaction.affected >= self.species.len() || interaction.by >= self.species.len() {
+				return Err(format!("interaction between unknown species {} and {}", interaction.affected, interaction.by));
+			}
+		}
+		return Ok(());
+	}
+
+	fn buckets_len(&self, initial_populations: &Vec<Vec<u8>>) -> usize {
+		let species_max = self.species.iter()
+			.map(|s| std::cmp::max(s.reset.max_timer(), s.newborn.max_timer()));
+		let initial_max = initial_populations.iter().flatten().copied();
+		return species_max.chain(initial_max).max().unwrap_or(0) as usize + 1;
+	}
+
+	// Runs a single simulation, returns the population of each species
+	pub fn run(&self, initial_populations: &Vec<Vec<u8>>, days: u16, rng: &mut Rng) -> Vec<u64> {
+		assert_eq!(initial_populations.len(), self.species.len(), "one initial population per species expected");
+		// The fields are public, so the models may not have gone through new
+		self.validate().unwrap_or_else(|e| panic!("{}", e));
+
+		let size = self.buckets_len(initial_populations);
+		let mut populations : Vec<Vec<u64>> = initial_populations.iter()
+			.map(|initial| {
+				let mut buckets = vec![0_u64; size];
+				for timer in initial { buckets[*timer as usize] += 1; }
+				buckets
+			})
+			.collect();
+
+		for _ in 0..days {
+			let totals : Vec<u64> = populations.iter().map(|b| b.iter().sum()).collect();
+			let total : u64 = totals.iter().sum();
+
+			for (idx, species) in self.species.iter().enumerate() {
+				let mut death_probability = species.death_probability;
+				for interaction in self.interactions.iter().filter(|i| i.affected == idx) {
+					if total > 0 {
+						death_probability += interaction.rate * totals[interaction.by] as f64 / total as f64;
+					}
+				}
+				let survival = 1.0 - death_probability.clamp(0.0, 1.0);
+
+				let mut population_tmp = vec![0_u64; size];
+				for (timer, count) in populations[idx].iter().enumerate() {
+					let alive = sample_binomial(*count, survival, rng);
+					if timer == 0 {
+						for (reset, amount) in species.reset.split(alive, rng) {
+							population_tmp[reset as usize] += amount;
+						}
+						for (newborn, amount) in species.newborn.split(alive, rng) {
+							population_tmp[newborn as usize] += amount;
+						}
+					}
+					else {
+						population_tmp[timer - 1] += alive;
+					}
+				}
+				populations[idx] = population_tmp;
+			}
+		}
+
+		return populations.iter().map(|b| b.iter().sum()).collect();
+	}
+
+	// Runs the simulation `runs` times and reports mean, variance and the
+	// requested percentiles of the final population.
+	// Every run uses its own generator derived from `seed`, so the report
+	// is reproducible.
+	pub fn run_many(&self, initial_populations: &Vec<Vec<u8>>, days: u16, runs: u32, seed: u64, percentiles: &[f64]) -> SimulationReport {
+		assert!(runs > 0, "at least one run is needed");
+
+		let mut seeds = Rng::new(seed);
+		let results : Vec<Vec<u64>> = (0..runs)
+			.map(|_| self.run(initial_populations, days, &mut Rng::new(seeds.next_u64())))
+			.collect();
+
+		let species = (0..self.species.len())
+			.map(|idx| {
+				let samples : Vec<u64> = results.iter().map(|r| r[idx]).collect();
+				PopulationStats::from_samples(&samples, percentiles)
+			})
+			.collect();
+
+		let totals : Vec<u64> = results.iter().map(|r| r.iter().sum()).collect();
+		let total = PopulationStats::from_samples(&totals, percentiles);
+
+		return SimulationReport { species, total };
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -197,5 +858,240 @@ mod tests {
 		let result = solver_optimized(&input.population, 256);
 		assert_eq!(result, 26984457539);
 	}
-	
+
+	#[test]
+	fn test_day6_lifecycle_default() {
+		let input = parser(INPUT_LITERAL);
+		let model = LifecycleModel::default();
+		assert_eq!(model.simulate(&input.population, 80), 5934);
+		assert_eq!(model.simulate(&input.population, 256), 26984457539);
+	}
+
+	#[test]
+	fn test_day6_lifecycle_custom() {
+		// A fish that spawns every 2 days and whose offspring spawn
+		// right away: the population doubles every 2 days
+		let model = LifecycleModel { reset_timer: 1, newborn_timer: 1, initial_delay: 0 };
+		assert_eq!(model.simulate(&vec![1], 6), 8);
+
+		// Delaying the initial population delays the whole curve
+		let model = LifecycleModel { initial_delay: 10, ..LifecycleModel::default() };
+		let input = parser(INPUT_LITERAL);
+		assert_eq!(model.simulate(&input.population, 10), 5);
+		assert_eq!(model.simulate(&input.population, 90), 5934);
+	}
+
+	#[test]
+	fn test_day6_matrix_matches_optimized() {
+		let input = parser(INPUT_LITERAL);
+		for days in 0..=400 {
+			let expected = solver_optimized(&input.population, days);
+			assert_eq!(solver_matrix::<u64>(&input.population, days as u64), expected);
+			assert_eq!(solver_matrix::<u128>(&input.population, days as u64), expected as u128);
+			assert_eq!(solver_matrix::<BigUint>(&input.population, days as u64).to_string(), expected.to_string());
+			assert_eq!(solver_matrix::<ModP<1_000_000_007>>(&input.population, days as u64).0, expected % 1_000_000_007);
+		}
+	}
+
+	#[test]
+	fn test_day6_matrix_past_u64() {
+		let input = parser(INPUT_LITERAL);
+
+		// u64 is not enough for 600 days, but u128 is
+		let big = solver_matrix::<BigUint>(&input.population, 600);
+		let wide = solver_matrix::<u128>(&input.population, 600);
+		assert!(wide > u64::MAX as u128);
+		assert_eq!(big.to_string(), wide.to_string());
+
+		// Modular arithmetic agrees with the exact count
+		let big = solver_matrix::<BigUint>(&input.population, 5000);
+		let modp = solver_matrix::<ModP<1_000_000_007>>(&input.population, 5000);
+		assert_eq!(big.rem_u32(1_000_000_007) as u64, modp.0);
+
+		// Same thing at 2^16 days, where the exact count has thousands of digits
+		let big = solver_matrix::<BigUint>(&input.population, 1 << 16);
+		let modp = solver_matrix::<ModP<1_000_000_007>>(&input.population, 1 << 16);
+		assert_eq!(big.rem_u32(1_000_000_007) as u64, modp.0);
+
+		// Huge number of days only make sense modulo something. M^(10^12)
+		// computed as M raised to the 10th power twelve times, instead of by
+		// repeated squaring
+		type P = ModP<1_000_000_007>;
+		let model = LifecycleModel::default();
+		let buckets = model.buckets(&input.population);
+		let mut power = model.transition_matrix::<P>(buckets.len());
+		for _ in 0..12 {
+			let base = power.clone();
+			for _ in 1..10 {
+				power = matrix_mul(&power, &base);
+			}
+		}
+		let mut expected = P::zero();
+		for row in &power {
+			for (col, count) in buckets.iter().enumerate() {
+				expected = expected.add(&row[col].mul(&P::from_u64(*count)));
+			}
+		}
+		let modp = solver_matrix::<P>(&input.population, 1_000_000_000_000);
+		assert_eq!(modp, expected);
+	}
+
+	#[test]
+	fn test_day6_solvers_agree() {
+		// Differential test: every solver should give the same result for
+		// random populations
+		let mut rng = Rng::new(2021);
+		for _ in 0..50 {
+			let size = rng.gen_range(1, 200);
+			let population : Vec<u8> = (0..size).map(|_| rng.gen_range(0, 9) as u8).collect();
+			let days = rng.gen_range(0, 257) as u16;
+
+			let expected = Optimized.population(&population, days);
+			assert_eq!(OptimizedHashmap.population(&population, days), expected);
+			assert_eq!(MatrixExponentiation.population(&population, days), expected);
+
+			// The unoptimized solver grows one entry per fish, keep it small
+			let days = days % 70;
+			assert_eq!(Unoptimized.population(&population, days), Optimized.population(&population, days));
+		}
+	}
+
+	#[test]
+	fn test_day6_time_series() {
+		let input = parser(INPUT_LITERAL);
+		let model = LifecycleModel::default();
+		let series : Vec<PopulationSnapshot> = model.time_series(&input.population).take(81).collect();
+
+		assert_eq!(series[0], PopulationSnapshot { day: 0, buckets: vec![0, 1, 1, 2, 1, 0, 0, 0, 0], total: 5 });
+		assert_eq!(series[1].buckets, vec![1, 1, 2, 1, 0, 0, 0, 0, 0]);
+		assert_eq!(series[18].total, 26);
+		assert_eq!(series[80].total, 5934);
+
+		// Stops when u64 is not enough
+		let last = model.time_series(&input.population).last().unwrap();
+		assert!(last.day > 256);
+		assert_eq!(last.total, solver_optimized(&input.population, last.day as u16));
+	}
+
+	#[test]
+	fn test_day6_time_series_export() {
+		let input = parser(INPUT_LITERAL);
+		let series : Vec<PopulationSnapshot> = LifecycleModel::default()
+			.time_series(&input.population)
+			.take(2)
+			.collect();
+
+		assert_eq!(time_series_to_csv(&series),
+"day,timer_0,timer_1,timer_2,timer_3,timer_4,timer_5,timer_6,timer_7,timer_8,total
+0,0,1,1,2,1,0,0,0,0,5
+1,1,1,2,1,0,0,0,0,0,5
+");
+		assert_eq!(time_series_to_json(&series),
+			"[{\"day\":0,\"buckets\":[0,1,1,2,1,0,0,0,0],\"total\":5},{\"day\":1,\"buckets\":[1,1,2,1,0,0,0,0,0],\"total\":5}]");
+	}
+
+	#[test]
+	fn test_day6_first_day_exceeding() {
+		let input = parser(INPUT_LITERAL);
+		let model = LifecycleModel::default();
+
+		assert_eq!(model.first_day_exceeding(&input.population, 4), Some(0));
+		assert_eq!(model.first_day_exceeding(&input.population, 5), Some(2));
+		assert_eq!(model.first_day_exceeding(&input.population, 26), Some(19));
+		assert_eq!(model.first_day_exceeding(&input.population, 26984457539), Some(257));
+		assert_eq!(model.first_day_exceeding(&input.population, 26984457538), Some(256));
+		assert_eq!(model.first_day_exceeding(&vec![], 10), None);
+
+		let day = model.first_day_exceeding(&input.population, u64::MAX).unwrap();
+		assert!(solver_matrix::<u128>(&input.population, day) > u64::MAX as u128);
+		assert!(solver_matrix::<u128>(&input.population, day - 1) <= u64::MAX as u128);
+
+		// Doubles every day, so it overflows a u64 before the linear scan ends
+		let doubling = LifecycleModel { reset_timer: 0, newborn_timer: 0, initial_delay: 0 };
+		assert_eq!(doubling.first_day_exceeding(&vec![0], u64::MAX), Some(64));
+		assert_eq!(doubling.first_day_exceeding(&vec![0], 1 << 63), Some(64));
+		assert_eq!(doubling.first_day_exceeding(&vec![0], (1 << 63) - 1), Some(63));
+		assert_eq!(doubling.first_day_exceeding(&vec![0, 0, 0], 1 << 40), Some(39));
+	}
+
+	#[test]
+	fn test_day6_stochastic_deterministic() {
+		// Without randomness every run is the deterministic answer
+		let input = parser(INPUT_LITERAL);
+		let simulation = StochasticSimulation::new(vec![LifecycleModel::default().into()]).unwrap();
+		let report = simulation.run_many(&vec![input.population.clone()], 80, 5, 1, &[0.05, 0.5, 0.95]);
+
+		assert_eq!(report.total.mean, 5934.0);
+		assert_eq!(report.total.variance, 0.0);
+		assert_eq!(report.total.percentiles, vec![(0.05, 5934), (0.5, 5934), (0.95, 5934)]);
+
+		let report = simulation.run_many(&vec![input.population.clone()], 256, 1, 1, &[0.5]);
+		assert_eq!(report.species[0].mean, 26984457539.0);
+	}
+
+	#[test]
+	fn test_day6_stochastic() {
+		let input = parser(INPUT_LITERAL);
+		let lanternfish : SpeciesModel = LifecycleModel::default().into();
+		let mortal = SpeciesModel { death_probability: 0.05, ..lanternfish.clone() };
+		let irregular = SpeciesModel {
+			reset: TimerDistribution::Uniform { low: 5, high: 7 },
+			newborn: TimerDistribution::Weighted(vec![(7, 1.0), (8, 3.0)]),
+			death_probability: 0.0
+		};
+
+		let populations = vec![input.population.clone(), input.population.clone(), input.population.clone()];
+		let mut simulation = StochasticSimulation::new(vec![lanternfish, mortal, irregular]).unwrap();
+		simulation.interactions.push(Interaction { affected: 0, by: 1, rate: 0.02 });
+
+		let report = simulation.run_many(&populations, 80, 200, 42, &[0.05, 0.5, 0.95]);
+
+		// Same seed, same report
+		assert_eq!(report, simulation.run_many(&populations, 80, 200, 42, &[0.05, 0.5, 0.95]));
+
+		// Mortality and predation can only make the population smaller
+		assert!(report.species[0].mean < 5934.0);
+		assert!(report.species[1].mean < report.species[0].mean);
+		assert!(report.species[1].variance > 0.0);
+		assert!(report.species[2].variance > 0.0);
+
+		for stats in report.species.iter().chain([&report.total]) {
+			let bands : Vec<u64> = stats.percentiles.iter().map(|(_, v)| *v).collect();
+			assert!(bands[0] <= bands[1] && bands[1] <= bands[2]);
+		}
+	}
+
+	#[test]
+	fn test_day6_binomial_small_mean() {
+		// n * p = 1, too small for the normal approximation
+		let mut rng = Rng::new(3);
+		let runs = 20000;
+		let samples : Vec<u64> = (0..runs).map(|_| sample_binomial(100, 0.01, &mut rng)).collect();
+		let mean = samples.iter().sum::<u64>() as f64 / runs as f64;
+		assert!((mean - 1.0).abs() < 0.05, "mean {}", mean);
+		// P(X = 0) = 0.99^100 = 0.366, P(X >= 3) = 0.079
+		let zeros = samples.iter().filter(|s| **s == 0).count() as f64 / runs as f64;
+		let three_or_more = samples.iter().filter(|s| **s >= 3).count() as f64 / runs as f64;
+		assert!((zeros - 0.366).abs() < 0.02, "zeros {}", zeros);
+		assert!((three_or_more - 0.079).abs() < 0.01, "three or more {}", three_or_more);
+
+		// Symmetric case, almost every fish is picked
+		let samples : Vec<u64> = (0..runs).map(|_| sample_binomial(100, 0.99, &mut rng)).collect();
+		let mean = samples.iter().sum::<u64>() as f64 / runs as f64;
+		assert!((mean - 99.0).abs() < 0.05, "mean {}", mean);
+	}
+
+	#[test]
+	fn test_day6_invalid_species() {
+		let lanternfish : SpeciesModel = LifecycleModel::default().into();
+		assert!(SpeciesModel::new(TimerDistribution::Weighted(vec![]), TimerDistribution::Fixed(8), 0.0).is_err());
+		assert!(SpeciesModel::new(TimerDistribution::Weighted(vec![(6, 0.0), (7, 0.0)]), TimerDistribution::Fixed(8), 0.0).is_err());
+		assert!(SpeciesModel::new(TimerDistribution::Weighted(vec![(6, f64::NAN)]), TimerDistribution::Fixed(8), 0.0).is_err());
+		assert!(SpeciesModel::new(TimerDistribution::Uniform { low: 7, high: 5 }, TimerDistribution::Fixed(8), 0.0).is_err());
+		assert!(SpeciesModel::new(TimerDistribution::Fixed(6), TimerDistribution::Fixed(8), 1.5).is_err());
+		assert!(SpeciesModel::new(TimerDistribution::Weighted(vec![(6, 0.0), (7, 1.0)]), TimerDistribution::Fixed(8), 0.0).is_ok());
+
+		let empty = SpeciesModel { newborn: TimerDistribution::Weighted(vec![]), ..lanternfish.clone() };
+		assert!(StochasticSimulation::new(vec![lanternfish, empty]).is_err());
+	}
 }
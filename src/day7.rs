@@ -8,51 +8,600 @@ pub struct Input {
 }
 
 #[aoc_generator(day7)]
-pub fn parser(input: &str) -> Vec<i32>{
-	return input.split(',').map(|s| s.parse::<i32>().unwrap()).collect();
+pub fn parser(input: &str) -> Vec<i64>{
+	return input.split(',').map(|s| s.parse::<i64>().unwrap()).collect();
+}
+
+pub fn linear_cost(distance:u64) -> u64 {
+	return distance;
+}
+
+pub fn triangular_cost(distance:u64) -> u64 {
+	// Divergent series 1+2+3+4...
+	// n (n+1)
+	// -------
+	//    2
+	//https://en.wikipedia.org/wiki/1_%2B_2_%2B_3_%2B_4_%2B_%E2%8B%AF
+	return (distance * (distance + 1)) / 2;
 }
 
 #[aoc(day7, part1)]
-pub fn solve_part1(input: &Vec<i32>) -> u32 {
-	fn distance_compute(distance:i32) -> i32 {
-		return distance;
-	}
-	return solver(&input, distance_compute);
+pub fn solve_part1(input: &Vec<i64>) -> u64 {
+	// The sum of distances is minimal at the median: moving away from it
+	// gets closer to less than half of the crabs and further from the rest
+	let mut sorted = input.clone();
+	sorted.sort();
+	let median = sorted[sorted.len() / 2];
+
+	return total_cost(input, median, &linear_cost);
 }
 
 #[aoc(day7, part2)]
-pub fn solve_part2(input: &Vec<i32>) -> u32 {
-	fn distance_compute(distance:i32) -> i32 {
-		return (distance * (distance + 1)) / 2;
+pub fn solve_part2(input: &Vec<i64>) -> u64 {
+	// d(d+1)/2 = (d^2 + d)/2. The d^2 part is minimal at the mean, and the d
+	// part can only move the optimum half a position away from it, so it is
+	// enough to check the integers around the mean
+	let sum : i64 = input.iter().sum();
+	let mean_floor = sum.div_euclid(input.len() as i64);
+
+	let min = *input.iter().min().unwrap();
+	let max = *input.iter().max().unwrap();
+
+	return ((mean_floor - 1)..=(mean_floor + 2))
+		.filter(|pos| *pos >= min && *pos <= max)
+		.map(|pos| total_cost(input, pos, &triangular_cost))
+		.min()
+		.unwrap();
+}
+
+#[aoc(day7, part1, Bruteforce)]
+pub fn solve_part1_bruteforce(input: &Vec<i64>) -> u64 {
+	return solver(input, linear_cost);
+}
+
+#[aoc(day7, part2, Bruteforce)]
+pub fn solve_part2_bruteforce(input: &Vec<i64>) -> u64 {
+	return solver(input, triangular_cost);
+}
+
+#[aoc(day7, part2, Ternary)]
+pub fn solve_part2_ternary(input: &Vec<i64>) -> u64 {
+	return solver_convex(input, triangular_cost);
+}
+
+// Fuel needed for all the crabs to move to `position`. Costs can be
+// "infinite" (u64::MAX, see the cost expressions), so the sum saturates
+fn total_cost<F: Fn(u64) -> u64>(input: &[i64], position: i64, distance_cost_fn: &F) -> u64 {
+	return input.iter()
+		.map(|sub_position| distance_cost_fn((position - sub_position).unsigned_abs()))
+		.fold(0, u64::saturating_add);
+}
+
+// Result of aligning the crabs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignmentResult {
+	// Every position with the minimum cost, in increasing order
+	pub positions: Vec<i64>,
+	// Fuel needed to align in any of the positions
+	pub cost: u64,
+	// (position, cost) for every position between the leftmost and the
+	// rightmost crab
+	pub curve: Vec<(i64, u64)>,
+}
+
+pub fn align<F: Fn(u64) -> u64>(input: &Vec<i64>, distance_cost_fn: F) -> AlignmentResult {
+	let min = *input.iter().min().unwrap();
+	let max = *input.iter().max().unwrap();
+
+	let curve : Vec<(i64, u64)> = (min..=max)
+		.map(|pos| (pos, total_cost(input, pos, &distance_cost_fn)))
+		.collect();
+
+	let cost = curve.iter().map(|(_, cost)| *cost).min().unwrap();
+	let positions = curve.iter()
+		.filter_map(|(pos, c)| if *c == cost { Some(*pos) } else { None })
+		.collect();
+
+	return AlignmentResult { positions, cost, curve };
+}
+
+// Works for any cost function trying every position between the leftmost and
+// the rightmost crab
+pub fn solver<F: Fn(u64) -> u64>(input: &Vec<i64>, distance_cost_fn: F) -> u64 {
+	return align(input, distance_cost_fn).cost;
+}
+
+// Same as solver, for cost functions that are convex (like d or d(d+1)/2).
+// The sum of convex functions is convex, so the total cost only goes down
+// and then up, and we can use a ternary search instead of trying every 
+// position.
+pub fn solver_convex<F: Fn(u64) -> u64>(input: &Vec<i64>, distance_cost_fn: F) -> u64 {
+	return ternary_search(input, &distance_cost_fn).1;
+}
+
+// Best (position, cost) for a convex cost function
+fn ternary_search<F: Fn(u64) -> u64>(input: &[i64], distance_cost_fn: &F) -> (i64, u64) {
+	let low = *input.iter().min().unwrap();
+	let high = *input.iter().max().unwrap();
+	return ternary_search_by(low, high, |pos| total_cost(input, pos, distance_cost_fn));
+}
+
+// Multiple rally points
+// ---------------------
+// With k meeting points, each crab goes to the closest one, so if we sort
+// the crabs by position every meeting point gets a contiguous run of crabs
+// (for convex costs). That lets us use dynamic programming:
+//
+//   best[j][i] = min over s of best[j-1][s] + group_cost(s..i)
+//
+// best cost of bringing the first i sorted crabs to j meeting points, where
+// the last group is formed by the crabs s..i.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RallyResult {
+	// Meeting points, in increasing order
+	pub points: Vec<i64>,
+	// For each meeting point, the indexes (in the input) of the crabs that go
+	// there
+	pub groups: Vec<Vec<usize>>,
+	// Total fuel
+	pub cost: u64,
+}
+
+// Best meeting point and its cost for the crabs sorted[start..end]
+trait GroupCost {
+	fn best(&self, start: usize, end: usize) -> (i64, u64);
+}
+
+// Linear cost: the median, with prefix sums to get the cost in O(1)
+struct LinearGroupCost<'a> {
+	sorted: &'a [i64],
+	// prefix[i] = sorted[0] + ... + sorted[i-1]
+	prefix: Vec<i128>,
+}
+
+// Sum of |x - position| for x in sorted[start..end], in O(log n)
+fn sum_distances(sorted: &[i64], prefix: &[i128], start: usize, end: usize, position: i64) -> i128 {
+	let split = start + sorted[start..end].partition_point(|x| *x <= position);
+	let p = position as i128;
+	let left = p * (split - start) as i128 - (prefix[split] - prefix[start]);
+	let right = (prefix[end] - prefix[split]) - p * (end - split) as i128;
+	return left + right;
+}
+
+impl<'a> GroupCost for LinearGroupCost<'a> {
+	fn best(&self, start: usize, end: usize) -> (i64, u64) {
+		// Lower median, any position between the two middle crabs is as good
+		let median = self.sorted[start + (end - start - 1) / 2];
+		let cost = sum_distances(self.sorted, &self.prefix, start, end, median);
+		return (median, cost as u64);
+	}
+}
+
+// Triangular cost: around the mean, using
+//   sum d(d+1)/2 = (sum d^2 + sum d) / 2
+// and prefix sums of x and x^2 to evaluate it
+struct TriangularGroupCost<'a> {
+	sorted: &'a [i64],
+	prefix: Vec<i128>,
+	prefix_squares: Vec<i128>,
+}
+
+impl<'a> TriangularGroupCost<'a> {
+	fn cost_at(&self, start: usize, end: usize, position: i64) -> u64 {
+		let p = position as i128;
+		let n = (end - start) as i128;
+		let sum = self.prefix[end] - self.prefix[start];
+		let sum_squares = self.prefix_squares[end] - self.prefix_squares[start];
+
+		let squares = sum_squares - 2 * p * sum + n * p * p;
+		let distances = sum_distances(self.sorted, &self.prefix, start, end, position);
+		return ((squares + distances) / 2) as u64;
+	}
+}
+
+impl<'a> GroupCost for TriangularGroupCost<'a> {
+	fn best(&self, start: usize, end: usize) -> (i64, u64) {
+		let sum = self.prefix[end] - self.prefix[start];
+		let mean_floor = sum.div_euclid((end - start) as i128) as i64;
+		let min = self.sorted[start];
+		let max = self.sorted[end - 1];
+
+		return ((mean_floor - 1)..=(mean_floor + 2))
+			.filter(|pos| *pos >= min && *pos <= max)
+			.map(|pos| (pos, self.cost_at(start, end, pos)))
+			.min_by_key(|(_, cost)| *cost)
+			.unwrap();
+	}
+}
+
+// Any convex cost function, with a ternary search for every group
+struct ConvexGroupCost<'a, F: Fn(u64) -> u64> {
+	sorted: &'a [i64],
+	distance_cost_fn: F,
+}
+
+impl<'a, F: Fn(u64) -> u64> GroupCost for ConvexGroupCost<'a, F> {
+	fn best(&self, start: usize, end: usize) -> (i64, u64) {
+		return ternary_search(&self.sorted[start..end], &self.distance_cost_fn);
+	}
+}
+
+fn prefix_sums(values: impl Iterator<Item = i128>) -> Vec<i128> {
+	let mut prefix = vec![0];
+	for value in values {
+		prefix.push(prefix.last().unwrap() + value);
+	}
+	return prefix;
+}
+
+// Indexes of the input sorted by crab position, and the sorted positions
+fn sort_crabs(input: &Vec<i64>) -> (Vec<usize>, Vec<i64>) {
+	let mut order : Vec<usize> = (0..input.len()).collect();
+	order.sort_by_key(|idx| input[*idx]);
+	let sorted = order.iter().map(|idx| input[*idx]).collect();
+	return (order, sorted);
+}
+
+pub fn k_rally_points_linear(input: &Vec<i64>, k: usize) -> RallyResult {
+	let (order, sorted) = sort_crabs(input);
+	let prefix = prefix_sums(sorted.iter().map(|x| *x as i128));
+	let group_cost = LinearGroupCost { sorted: &sorted, prefix };
+	return k_rally_points_with(&order, k, &group_cost);
+}
+
+pub fn k_rally_points_triangular(input: &Vec<i64>, k: usize) -> RallyResult {
+	let (order, sorted) = sort_crabs(input);
+	let prefix = prefix_sums(sorted.iter().map(|x| *x as i128));
+	let prefix_squares = prefix_sums(sorted.iter().map(|x| *x as i128 * *x as i128));
+	let group_cost = TriangularGroupCost { sorted: &sorted, prefix, prefix_squares };
+	return k_rally_points_with(&order, k, &group_cost);
+}
+
+// For any convex cost function. Much slower than the specialized versions,
+// every group needs a ternary search
+pub fn k_rally_points<F: Fn(u64) -> u64>(input: &Vec<i64>, k: usize, distance_cost_fn: F) -> RallyResult {
+	let (order, sorted) = sort_crabs(input);
+	let group_cost = ConvexGroupCost { sorted: &sorted, distance_cost_fn };
+	return k_rally_points_with(&order, k, &group_cost);
+}
+
+fn k_rally_points_with(order: &[usize], k: usize, group_cost: &impl GroupCost) -> RallyResult {
+	assert!(k > 0, "at least one rally point is needed");
+	let n = order.len();
+	// There is no point in having more meeting points than crabs
+	let k = std::cmp::min(k, n);
+
+	// Cost of every possible group, computed once
+	let mut groups = vec![vec![(0_i64, 0_u64); n + 1]; n + 1];
+	for (start, row) in groups.iter_mut().enumerate() {
+		for (end, group) in row.iter_mut().enumerate().skip(start + 1) {
+			*group = group_cost.best(start, end);
+		}
+	}
+
+	// best[j][i] and the start of the last group to rebuild the solution
+	let mut best = vec![vec![u64::MAX; n + 1]; k + 1];
+	let mut last_group_start = vec![vec![0_usize; n + 1]; k + 1];
+	best[0][0] = 0;
+
+	for j in 1..=k {
+		for i in j..=n {
+			for start in (j - 1)..i {
+				if best[j - 1][start] == u64::MAX { continue; }
+				let cost = best[j - 1][start].saturating_add(groups[start][i].1);
+				if cost < best[j][i] {
+					best[j][i] = cost;
+					last_group_start[j][i] = start;
+				}
+			}
+		}
+	}
+
+	// Walk back from the last group
+	let mut points = Vec::new();
+	let mut crab_groups = Vec::new();
+	let mut end = n;
+	for j in (1..=k).rev() {
+		let start = last_group_start[j][end];
+		points.push(groups[start][end].0);
+		crab_groups.push(order[start..end].to_vec());
+		end = start;
+	}
+	points.reverse();
+	crab_groups.reverse();
+
+	return RallyResult { points, groups: crab_groups, cost: best[k][n] };
+}
+
+// Crabs on a plane
+// ----------------
+// Crabs at (x, y) positions, the cost of moving depends on the Manhattan
+// distance |dx| + |dy|.
+
+pub type PlanePosition = (i64, i64);
+
+// Accepts one "x,y" crab per line. A single line is always the 1D puzzle
+// input (comma separated x positions), with every crab at y = 0, so a map
+// on the plane needs at least two crabs.
+pub fn parser_plane(input: &str) -> Vec<PlanePosition> {
+	let lines : Vec<&str> = input.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect();
+	if lines.len() == 1 {
+		return line_to_plane(&parser(lines[0]));
+	}
+
+	return lines.iter()
+		.map(|line| {
+			let (x, y) = line.split_once(',').unwrap();
+			(x.trim().parse::<i64>().unwrap(), y.trim().parse::<i64>().unwrap())
+		})
+		.collect();
+}
+
+// 1D positions as crabs on the plane, all of them at y = 0
+pub fn line_to_plane(positions: &[i64]) -> Vec<PlanePosition> {
+	return positions.iter().map(|x| (*x, 0)).collect();
+}
+
+pub enum PlaneCost<F: Fn(u64) -> u64> {
+	// cost(|dx|) + cost(|dy|): every axis can be solved on its own. With a
+	// linear cost this is the Manhattan distance, and the answer is the
+	// median of each axis
+	Separable(F),
+	// cost(|dx| + |dy|)
+	Manhattan(F),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaneAlignment {
+	pub position: PlanePosition,
+	pub cost: u64,
+}
+
+// Saturates like total_cost
+fn total_cost_plane<F: Fn(u64) -> u64>(crabs: &[PlanePosition], position: PlanePosition, distance_cost_fn: &F) -> u64 {
+	return crabs.iter()
+		.map(|(x, y)| distance_cost_fn((position.0 - x).unsigned_abs().saturating_add((position.1 - y).unsigned_abs())))
+		.fold(0, u64::saturating_add);
+}
+
+// The cost must be convex (and non decreasing) for the search to find the
+// optimum
+pub fn align_plane<F: Fn(u64) -> u64>(crabs: &Vec<PlanePosition>, cost: PlaneCost<F>) -> PlaneAlignment {
+	return match cost {
+		PlaneCost::Separable(distance_cost_fn) => {
+			let xs : Vec<i64> = crabs.iter().map(|c| c.0).collect();
+			let ys : Vec<i64> = crabs.iter().map(|c| c.1).collect();
+			let (x, cost_x) = ternary_search(&xs, &distance_cost_fn);
+			let (y, cost_y) = ternary_search(&ys, &distance_cost_fn);
+			PlaneAlignment { position: (x, y), cost: cost_x.saturating_add(cost_y) }
+		},
+		PlaneCost::Manhattan(distance_cost_fn) => {
+			// A convex cost of the Manhattan distance is convex in the plane,
+			// so we do a ternary search over x where each step is a ternary
+			// search over y
+			let min_y = crabs.iter().map(|c| c.1).min().unwrap();
+			let max_y = crabs.iter().map(|c| c.1).max().unwrap();
+			let best_for_x = |x: i64| {
+				ternary_search_by(min_y, max_y, |y| total_cost_plane(crabs, (x, y), &distance_cost_fn))
+			};
+
+			let min_x = crabs.iter().map(|c| c.0).min().unwrap();
+			let max_x = crabs.iter().map(|c| c.0).max().unwrap();
+			let (x, (y, cost)) = ternary_search_by_key(min_x, max_x, best_for_x);
+			PlaneAlignment { position: (x, y), cost }
+		},
+	};
+}
+
+// Integer ternary search over low..=high of a convex function
+fn ternary_search_by<C: Fn(i64) -> u64>(low: i64, high: i64, cost_fn: C) -> (i64, u64) {
+	let (pos, (_, cost)) = ternary_search_by_key(low, high, |pos| ((), cost_fn(pos)));
+	return (pos, cost);
+}
+
+// Same, for functions returning extra data along with the cost
+fn ternary_search_by_key<T, C: Fn(i64) -> (T, u64)>(low: i64, high: i64, cost_fn: C) -> (i64, (T, u64)) {
+	let mut low = low;
+	let mut high = high;
+
+	while high - low > 2 {
+		let third = (high - low) / 3;
+		let m1 = low + third;
+		let m2 = high - third;
+		let cost_m1 = cost_fn(m1).1;
+		let cost_m2 = cost_fn(m2).1;
+
+		if cost_m1 < cost_m2 { high = m2 - 1; }
+		else if cost_m1 > cost_m2 { low = m1 + 1; }
+		// Being convex, equal costs means the minimum is between them
+		else { low = m1; high = m2; }
+	}
+
+	return (low..=high)
+		.map(|pos| (pos, cost_fn(pos)))
+		.min_by_key(|(_, (_, cost))| *cost)
+		.unwrap();
+}
+
+// Cost expressions
+// ----------------
+// Small expression language to define the fuel cost of moving a distance
+// `d` at runtime, e.g. "d*(d+1)/2" or "min(d, 10)*3".
+//
+// expr   := term (('+' | '-') term)*
+// term   := factor (('*' | '/' | '%') factor)*
+// factor := number | 'd' | ('min' | 'max') '(' expr ',' expr ')' | '(' expr ')'
+//
+// Values are u64. Operations saturate instead of overflowing, and dividing
+// by zero gives u64::MAX, an "infinite" cost.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+	Number(u64),
+	Distance,
+	Add(Box<Expr>, Box<Expr>),
+	Sub(Box<Expr>, Box<Expr>),
+	Mul(Box<Expr>, Box<Expr>),
+	Div(Box<Expr>, Box<Expr>),
+	Rem(Box<Expr>, Box<Expr>),
+	Min(Box<Expr>, Box<Expr>),
+	Max(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+	fn eval(&self, d: u64) -> u64 {
+		return match self {
+			Expr::Number(n) => *n,
+			Expr::Distance => d,
+			Expr::Add(a, b) => a.eval(d).saturating_add(b.eval(d)),
+			Expr::Sub(a, b) => a.eval(d).saturating_sub(b.eval(d)),
+			Expr::Mul(a, b) => a.eval(d).saturating_mul(b.eval(d)),
+			Expr::Div(a, b) => a.eval(d).checked_div(b.eval(d)).unwrap_or(u64::MAX),
+			Expr::Rem(a, b) => a.eval(d).checked_rem(b.eval(d)).unwrap_or(u64::MAX),
+			Expr::Min(a, b) => std::cmp::min(a.eval(d), b.eval(d)),
+			Expr::Max(a, b) => std::cmp::max(a.eval(d), b.eval(d)),
+		};
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CostExpression {
+	root: Expr
+}
+
+impl CostExpression {
+	pub fn parse(expression: &str) -> Result<Self, String> {
+		let tokens = tokenize(expression)?;
+		let mut parser = ExprParser { tokens, pos: 0 };
+		let root = parser.expr()?;
+		if let Some(token) = parser.peek() {
+			return Err(format!("unexpected '{}' at token {}", token, parser.pos));
+		}
+		return Ok(CostExpression { root });
+	}
+
+	pub fn eval(&self, distance: u64) -> u64 {
+		return self.root.eval(distance);
 	}
-	return solver(&input, distance_compute);
 }
 
-pub fn solver(input: &Vec<i32>, distance_cost_fn: fn (i32) -> i32) -> u32 {
-	let min = *input.iter().min().unwrap() as usize;
-	let max = *input.iter().max().unwrap() as usize;
-	
-	let h_pos_lenght = max - min;
-	let mut h_positions : Vec<i32> = vec![0; h_pos_lenght];
+impl std::str::FromStr for CostExpression {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, String> {
+		return CostExpression::parse(s);
+	}
+}
 
-	for possible_pos in 0..h_pos_lenght {
-		for sub_position in input {
-			let distance = i32::abs(possible_pos as i32 - *sub_position as i32);
+fn tokenize(expression: &str) -> Result<Vec<String>, String> {
+	let mut tokens = Vec::new();
+	let mut chars = expression.chars().peekable();
 
-			// Divergent series 1+2+3+4...
-			// n (n+1)
-			// -------
-			//    2
-			//https://en.wikipedia.org/wiki/1_%2B_2_%2B_3_%2B_4_%2B_%E2%8B%AF
-			h_positions[possible_pos as usize] += distance_cost_fn(distance);
+	while let Some(&c) = chars.peek() {
+		if c.is_whitespace() {
+			chars.next();
+		}
+		else if c.is_ascii_alphanumeric() {
+			// numbers and names
+			let mut token = String::new();
+			while let Some(&c) = chars.peek() {
+				if !c.is_ascii_alphanumeric() && c != '_' { break; }
+				token.push(c);
+				chars.next();
+			}
+			tokens.push(token);
+		}
+		else if "+-*/%(),".contains(c) {
+			tokens.push(c.to_string());
+			chars.next();
+		}
+		else {
+			return Err(format!("unexpected character '{}'", c));
 		}
 	}
+	return Ok(tokens);
+}
+
+// Recursive descent parser, one function per grammar rule
+struct ExprParser {
+	tokens: Vec<String>,
+	pos: usize,
+}
 
-	return *h_positions.iter().min().unwrap() as u32;
+impl ExprParser {
+	fn peek(&self) -> Option<&str> {
+		return self.tokens.get(self.pos).map(|t| t.as_str());
+	}
+
+	fn next(&mut self) -> Option<String> {
+		let token = self.tokens.get(self.pos).cloned();
+		self.pos += 1;
+		return token;
+	}
+
+	fn expect(&mut self, expected: &str) -> Result<(), String> {
+		return match self.next() {
+			Some(token) if token == expected => Ok(()),
+			Some(token) => Err(format!("expected '{}', found '{}'", expected, token)),
+			None => Err(format!("expected '{}', found end of expression", expected)),
+		};
+	}
+
+	fn expr(&mut self) -> Result<Expr, String> {
+		let mut left = self.term()?;
+		while let Some(op) = self.peek() {
+			left = match op {
+				"+" => { self.next(); Expr::Add(Box::new(left), Box::new(self.term()?)) },
+				"-" => { self.next(); Expr::Sub(Box::new(left), Box::new(self.term()?)) },
+				_ => break,
+			};
+		}
+		return Ok(left);
+	}
+
+	fn term(&mut self) -> Result<Expr, String> {
+		let mut left = self.factor()?;
+		while let Some(op) = self.peek() {
+			left = match op {
+				"*" => { self.next(); Expr::Mul(Box::new(left), Box::new(self.factor()?)) },
+				"/" => { self.next(); Expr::Div(Box::new(left), Box::new(self.factor()?)) },
+				"%" => { self.next(); Expr::Rem(Box::new(left), Box::new(self.factor()?)) },
+				_ => break,
+			};
+		}
+		return Ok(left);
+	}
+
+	fn factor(&mut self) -> Result<Expr, String> {
+		let token = self.next().ok_or("unexpected end of expression")?;
+
+		if token == "(" {
+			let inner = self.expr()?;
+			self.expect(")")?;
+			return Ok(inner);
+		}
+		if token == "d" {
+			return Ok(Expr::Distance);
+		}
+		if token == "min" || token == "max" {
+			self.expect("(")?;
+			let a = Box::new(self.expr()?);
+			self.expect(",")?;
+			let b = Box::new(self.expr()?);
+			self.expect(")")?;
+			return Ok(if token == "min" { Expr::Min(a, b) } else { Expr::Max(a, b) });
+		}
+		return token.parse::<u64>()
+			.map(Expr::Number)
+			.map_err(|_| format!("unexpected '{}'", token));
+	}
 }
 
+#[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::rng::Rng;
 
 	const INPUT_LITERAL : &str = "16,1,2,0,4,2,7,1,2,14";
  
@@ -69,5 +618,292 @@ mod tests {
 		let result = solve_part2(&input);
 		assert_eq!(result, 168);
 	}
-	
-}
\ No newline at end of file
+
+	#[test]
+	fn test_day7_offset_positions() {
+		// No crab at 0, and for the linear cost the best position is the 
+		// rightmost one
+		let input = parser("100,103,103");
+		assert_eq!(solver(&input, linear_cost), 3);
+		assert_eq!(solve_part1(&input), 3);
+		assert_eq!(solver(&input, triangular_cost), 3 + 1 + 1);
+		assert_eq!(solve_part2(&input), 3 + 1 + 1);
+		assert_eq!(solver_convex(&input, triangular_cost), 3 + 1 + 1);
+	}
+
+	#[test]
+	fn test_day7_solvers_agree() {
+		let mut rng = Rng::new(7);
+		for _ in 0..100 {
+			let size = rng.gen_range(1, 30);
+			let offset = rng.gen_range(0, 1000) as i64;
+			let input : Vec<i64> = (0..size).map(|_| offset + rng.gen_range(0, 50) as i64).collect();
+
+			assert_eq!(solve_part1(&input), solver(&input, linear_cost));
+			assert_eq!(solver_convex(&input, linear_cost), solver(&input, linear_cost));
+			assert_eq!(solve_part2(&input), solver(&input, triangular_cost));
+			assert_eq!(solver_convex(&input, triangular_cost), solver(&input, triangular_cost));
+		}
+	}
+
+	#[test]
+	fn test_day7_alignment() {
+		let input = parser(INPUT_LITERAL);
+
+		let result = align(&input, linear_cost);
+		assert_eq!(result.positions, vec![2]);
+		assert_eq!(result.cost, 37);
+		assert_eq!(result.curve.len(), 17);
+		assert_eq!(result.curve[0], (0, 49));
+		assert_eq!(result.curve[1], (1, 41));
+		assert_eq!(result.curve[3], (3, 39));
+		assert_eq!(result.curve[10], (10, 71));
+
+		let result = align(&input, triangular_cost);
+		assert_eq!(result.positions, vec![5]);
+		assert_eq!(result.cost, 168);
+		assert_eq!(result.curve[2], (2, 206));
+
+		// Ties report every position
+		let result = align(&parser("1,5"), linear_cost);
+		assert_eq!(result.positions, vec![1, 2, 3, 4, 5]);
+		assert_eq!(result.cost, 4);
+	}
+
+	#[test]
+	fn test_day7_large_distances() {
+		// 100000 * 100001 doesn't fit in an i32
+		let input = parser("0,100000");
+		assert_eq!(solve_part2(&input), 2 * (50000 * 50001 / 2));
+		assert_eq!(solver_convex(&input, triangular_cost), 2 * (50000 * 50001 / 2));
+	}
+
+	#[test]
+	fn test_day7_closures() {
+		let input = parser(INPUT_LITERAL);
+		let factor = 3;
+		assert_eq!(solver(&input, |d| d * factor), 37 * 3);
+		assert_eq!(solver_convex(&input, |d| d * factor), 37 * 3);
+	}
+
+	#[test]
+	fn test_day7_cost_expressions() {
+		let input = parser(INPUT_LITERAL);
+
+		let linear = CostExpression::parse("d").unwrap();
+		assert_eq!(solver(&input, |d| linear.eval(d)), 37);
+
+		let triangular : CostExpression = "d * (d + 1) / 2".parse().unwrap();
+		assert_eq!(solver(&input, |d| triangular.eval(d)), 168);
+
+		let capped = CostExpression::parse("min(d, 10)*3").unwrap();
+		assert_eq!(capped.eval(4), 12);
+		assert_eq!(capped.eval(40), 30);
+
+		let expression = CostExpression::parse("max(d*d, 5) - 2 % 3 + 10/0").unwrap();
+		assert_eq!(expression.eval(1), u64::MAX);
+		assert_eq!(CostExpression::parse("2 + 3 * 4").unwrap().eval(0), 14);
+		assert_eq!(CostExpression::parse("(2 + 3) * 4").unwrap().eval(0), 20);
+		assert_eq!(CostExpression::parse("1 - d").unwrap().eval(5), 0);
+	}
+
+	#[test]
+	fn test_day7_infinite_cost() {
+		// Every distance costs u64::MAX, the total must not overflow
+		let input = parser(INPUT_LITERAL);
+		let infinite = CostExpression::parse("d/0").unwrap();
+		let result = align(&input, |d| infinite.eval(d));
+		assert_eq!(result.cost, u64::MAX);
+		assert_eq!(result.positions.len(), 17);
+		assert_eq!(solver(&input, |d| infinite.eval(d)), u64::MAX);
+		assert_eq!(solver_convex(&input, |d| infinite.eval(d)), u64::MAX);
+
+		// Only staying still is free, so no position is better than another
+		let stay = CostExpression::parse("d*(1000/(1-min(d,1)))").unwrap();
+		assert_eq!(stay.eval(0), 0);
+		assert_eq!(stay.eval(3), u64::MAX);
+		assert_eq!(solver(&parser("1,1,5"), |d| stay.eval(d)), u64::MAX);
+	}
+
+	#[test]
+	fn test_day7_cost_expression_errors() {
+		assert!(CostExpression::parse("").is_err());
+		assert!(CostExpression::parse("d +").is_err());
+		assert!(CostExpression::parse("(d").is_err());
+		assert!(CostExpression::parse("x * 2").is_err());
+		assert!(CostExpression::parse("d ^ 2").is_err());
+		assert!(CostExpression::parse("min(d)").is_err());
+		assert!(CostExpression::parse("d d").is_err());
+	}
+
+	#[test]
+	fn test_day7_single_rally_point() {
+		let input = parser(INPUT_LITERAL);
+		assert_eq!(k_rally_points_linear(&input, 1).cost, 37);
+		assert_eq!(k_rally_points_triangular(&input, 1).cost, 168);
+		assert_eq!(k_rally_points(&input, 1, triangular_cost).cost, 168);
+		assert_eq!(k_rally_points_triangular(&input, 1).points, vec![5]);
+	}
+
+	#[test]
+	fn test_day7_k_rally_points() {
+		let input = parser(INPUT_LITERAL);
+
+		// 14 and 16 meet at 14, the rest at 2
+		let result = k_rally_points_linear(&input, 2);
+		assert_eq!(result.points, vec![2, 14]);
+		assert_eq!(result.groups, vec![vec![3, 1, 7, 2, 5, 8, 4, 6], vec![9, 0]]);
+		assert_eq!(result.cost, 11 + 2);
+
+		// As many points as crab positions, nobody moves
+		assert_eq!(k_rally_points_linear(&input, 7).cost, 0);
+		assert_eq!(k_rally_points_triangular(&input, 20).cost, 0);
+		assert_eq!(k_rally_points_triangular(&input, 20).points.len(), 10);
+	}
+
+	#[test]
+	fn test_day7_k_rally_points_agree() {
+		let mut rng = Rng::new(35);
+		for _ in 0..30 {
+			let size = rng.gen_range(1, 25);
+			let input : Vec<i64> = (0..size).map(|_| rng.gen_range(0, 100) as i64).collect();
+			let k = rng.gen_range(1, 5) as usize;
+
+			let linear = k_rally_points_linear(&input, k);
+			assert_eq!(linear.cost, k_rally_points(&input, k, linear_cost).cost);
+
+			let triangular = k_rally_points_triangular(&input, k);
+			assert_eq!(triangular.cost, k_rally_points(&input, k, triangular_cost).cost);
+
+			// The reported cost matches the reported groups
+			let cost : u64 = triangular.groups.iter().zip(&triangular.points)
+				.map(|(group, point)| group.iter().map(|idx| triangular_cost((input[*idx] - point).unsigned_abs())).sum::<u64>())
+				.sum();
+			assert_eq!(cost, triangular.cost);
+			assert_eq!(triangular.groups.iter().map(|g| g.len()).sum::<usize>(), input.len());
+		}
+	}
+
+	// Best total cost trying every way to split the crabs in k groups, and
+	// every meeting point for each group
+	fn brute_force_rally_points(input: &[i64], k: usize, cost_fn: fn(u64) -> u64) -> u64 {
+		let mut best = u64::MAX;
+		let mut assignment = vec![0; input.len()];
+		loop {
+			let cost = (0..k)
+				.map(|group| {
+					let crabs : Vec<i64> = input.iter().zip(&assignment)
+						.filter(|(_, g)| **g == group)
+						.map(|(x, _)| *x)
+						.collect();
+					if crabs.is_empty() { 0 } else { solver(&crabs, cost_fn) }
+				})
+				.sum();
+			best = std::cmp::min(best, cost);
+
+			// Next assignment, counting in base k
+			let mut idx = 0;
+			while idx < assignment.len() && assignment[idx] == k - 1 {
+				assignment[idx] = 0;
+				idx += 1;
+			}
+			if idx == assignment.len() {
+				return best;
+			}
+			assignment[idx] += 1;
+		}
+	}
+
+	#[test]
+	fn test_day7_k_rally_points_brute_force() {
+		let mut rng = Rng::new(135);
+		for _ in 0..40 {
+			let size = rng.gen_range(1, 9) as usize;
+			let input : Vec<i64> = (0..size).map(|_| rng.gen_range(0, 30) as i64).collect();
+			let k = rng.gen_range(1, 4) as usize;
+
+			let cost_fns : [fn(u64) -> u64; 2] = [linear_cost, triangular_cost];
+			let results = [k_rally_points_linear(&input, k), k_rally_points_triangular(&input, k)];
+			for (cost_fn, result) in cost_fns.iter().zip(&results) {
+				assert_eq!(result.cost, brute_force_rally_points(&input, k, *cost_fn), "{:?} k={}", input, k);
+
+				// Every crab goes to exactly one point
+				let mut crabs : Vec<usize> = result.groups.concat();
+				crabs.sort();
+				assert_eq!(crabs, (0..size).collect::<Vec<usize>>());
+
+				// Every point is the best one for its group, and the groups
+				// add up to the total
+				let mut total = 0;
+				for (group, point) in result.groups.iter().zip(&result.points) {
+					let positions : Vec<i64> = group.iter().map(|idx| input[*idx]).collect();
+					let cost = total_cost(&positions, *point, cost_fn);
+					assert_eq!(cost, solver(&positions, cost_fn));
+					total += cost;
+				}
+				assert_eq!(total, result.cost);
+			}
+		}
+	}
+
+	#[test]
+	fn test_day7_plane_1d_input() {
+		let crabs = parser_plane(INPUT_LITERAL);
+		assert_eq!(crabs.len(), 10);
+		assert_eq!(crabs[0], (16, 0));
+
+		let result = align_plane(&crabs, PlaneCost::Separable(linear_cost));
+		assert_eq!(result, PlaneAlignment { position: (2, 0), cost: 37 });
+		let result = align_plane(&crabs, PlaneCost::Manhattan(triangular_cost));
+		assert_eq!(result, PlaneAlignment { position: (5, 0), cost: 168 });
+
+		// A single line is the 1D input, whatever the number of crabs
+		assert_eq!(parser_plane("3,4"), vec![(3, 0), (4, 0)]);
+		assert_eq!(parser_plane("3,4,5"), vec![(3, 0), (4, 0), (5, 0)]);
+		assert_eq!(parser_plane("7"), vec![(7, 0)]);
+		assert_eq!(parser_plane("3,4\n5,6"), vec![(3, 4), (5, 6)]);
+	}
+
+	#[test]
+	fn test_day7_plane() {
+		let crabs = parser_plane("0,0\n10,0\n0,10\n1,1\n2,2");
+
+		// Medians of each axis
+		let result = align_plane(&crabs, PlaneCost::Separable(linear_cost));
+		assert_eq!(result, PlaneAlignment { position: (1, 1), cost: 2 + 10 + 10 + 2 });
+		assert_eq!(align_plane(&crabs, PlaneCost::Manhattan(linear_cost)).cost, result.cost);
+	}
+
+	#[test]
+	fn test_day7_plane_infinite_cost() {
+		let crabs = parser_plane("0,0\n10,0\n0,10");
+		let infinite = CostExpression::parse("d/0").unwrap();
+		let result = align_plane(&crabs, PlaneCost::Manhattan(|d| infinite.eval(d)));
+		assert_eq!(result.cost, u64::MAX);
+		let result = align_plane(&crabs, PlaneCost::Separable(|d| infinite.eval(d)));
+		assert_eq!(result.cost, u64::MAX);
+	}
+
+	#[test]
+	fn test_day7_plane_search() {
+		// Compare the search against trying every cell
+		let mut rng = Rng::new(36);
+		for _ in 0..50 {
+			let size = rng.gen_range(1, 15);
+			let crabs : Vec<PlanePosition> = (0..size)
+				.map(|_| (rng.gen_range(0, 30) as i64, rng.gen_range(0, 30) as i64))
+				.collect();
+
+			let mut best = u64::MAX;
+			for x in 0..30 {
+				for y in 0..30 {
+					best = std::cmp::min(best, total_cost_plane(&crabs, (x, y), &triangular_cost));
+				}
+			}
+
+			let result = align_plane(&crabs, PlaneCost::Manhattan(triangular_cost));
+			assert_eq!(result.cost, best);
+			assert_eq!(total_cost_plane(&crabs, result.position, &triangular_cost), best);
+		}
+	}
+}
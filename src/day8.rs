@@ -1,25 +1,34 @@
 use aoc_runner_derive::aoc;
 use aoc_runner_derive::aoc_generator;
-use std::collections::HashMap;
-use std::collections::HashSet;
+
+use crate::rng::Rng;
 
 // https://adventofcode.com/2021/day/8
 
+// Every pattern is stored as a bitmask of the wires that are on,
+// a = bit 0 .. g = bit 6, so comparing patterns or finding the wires two
+// patterns have in common are single operations.
 pub struct Input{
-	signals: Vec<Vec<String>>,
-	digits:  Vec<Vec<String>>
+	signals: Vec<Vec<u8>>,
+	digits:  Vec<Vec<u8>>
 }
 
 #[aoc_generator(day8)]
 pub fn parser(input: &str) -> Input{
-	let mut signals : Vec<Vec<String>> = Vec::new();
-	let mut digits : Vec<Vec<String>> = Vec::new();
+	let mut signals : Vec<Vec<u8>> = Vec::new();
+	let mut digits : Vec<Vec<u8>> = Vec::new();
+
+	fn parse_patterns(patterns: &str) -> Vec<u8> {
+		return patterns.split_whitespace()
+			.map(|p| parse_pattern(p).unwrap_or_else(|e| panic!("{}", e)))
+			.collect();
+	}
 
 	for line in input.lines() {
 		let mut parts = line.split("|");
 
-		let line_signals = parts.next().unwrap().split_whitespace().map(String::from).collect();
-		let line_digits = parts.next().unwrap().split_whitespace().map(String::from).collect();
+		let line_signals = parse_patterns(parts.next().unwrap());
+		let line_digits = parse_patterns(parts.next().unwrap());
 
 		signals.push(line_signals);
 		digits.push(line_digits);
@@ -31,6 +40,10 @@ pub fn parser(input: &str) -> Input{
 	}
 }
 
+pub fn parse_pattern(pattern: &str) -> Result<u8, String> {
+	return parse_wires(pattern, 7).map(|mask| mask as u8);
+}
+
 #[aoc(day8, part1)]
 pub fn solve_part1(input: &Input) -> u32 {
 	let mut count = 0;
@@ -38,8 +51,7 @@ pub fn solve_part1(input: &Input) -> u32 {
 	for vec_4_digits in &input.digits {
 
 		for digit in vec_4_digits {
-			let char_count = digit.chars().count();
-			match char_count {
+			match digit.count_ones() {
 				2 | 3 | 4 | 7 => count += 1,
 				_ => ()
 			}
@@ -50,104 +62,611 @@ pub fn solve_part1(input: &Input) -> u32 {
 }
 
 #[aoc(day8, part2)]
-pub fn solve_part2(input: &Input) -> u32 {
+pub fn solve_part2(input: &Input) -> u128 {
+	// Displays can have any number of digits, so the values (and the sum)
+	// are u128, and we check for overflows instead of wrapping around
+	return input.signals.iter().zip(&input.digits)
+		.enumerate()
+		.map(|(idx, (signals, digits))| {
+			match decode_entry(signals, digits) {
+				Ok(decoded) => decoded.value().unwrap_or_else(|| panic!("entry {}: too many digits", idx)),
+				Err(e) => panic!("entry {}: {:?}", idx, e),
+			}
+		})
+		.fold(0_u128, |acc, value| acc.checked_add(value).expect("sum overflowed u128"));
+}
+
+// First version, finds the digits through a fixed sequence of set
+// differences. Needs all the ten patterns of every entry, and fails on
+// entries that don't decode (a missing pattern, or an output that is not
+// one of the signals).
+#[aoc(day8, part2, SetDifference)]
+pub fn solve_part2_set_difference(input: &Input) -> Result<u128, String> {
 
-	let mut digits_vector : Vec<u32> = Vec::new();
+	let mut digits_vector : Vec<u128> = Vec::new();
 
 	for idx in 0..input.digits.len() {
 
-		// in this hashmap we will map a digit to a signal set
-		let mut digits : HashMap<u8, HashSet<char>> = HashMap::new();
-		let mut signals_len_6 : Vec<HashSet<char>> = Vec::new();
-		let mut signals_len_5 : Vec<HashSet<char>> = Vec::new();
-		for signal_string in &input.signals[idx] {
-			for signal in signal_string.split_whitespace() {
-				let char_hashmap = HashSet::from_iter(signal.chars());
-				let char_count = char_hashmap.len() as u8;
-
-				// Digits 1,7,4,8 are inmmediate to locate
-				if char_count == 2 { digits.entry(1).or_insert(char_hashmap); }
-				else if char_count == 3 { digits.entry(7).or_insert(char_hashmap); }
-				else if char_count == 4 { digits.entry(4).or_insert(char_hashmap); }
-				else if char_count == 7 { digits.entry(8).or_insert(char_hashmap); }
-				else if char_count == 6 { signals_len_6.push(char_hashmap)}
-				else if char_count == 5 { signals_len_5.push(char_hashmap)}
-				else {
-					panic!("");
-				}
+		// pattern (as a bitmask, so 0..128) -> digit
+		let mut digits = [u8::MAX; 128];
+		let mut signals_len_6 : Vec<u8> = Vec::new();
+		let mut signals_len_5 : Vec<u8> = Vec::new();
+		let mut signals_1 = 0;
+		let mut signals_4 = 0;
+		for signal in &input.signals[idx] {
+			let signal = *signal;
+
+			// Digits 1,7,4,8 are inmmediate to locate
+			match signal.count_ones() {
+				2 => { digits[signal as usize] = 1; signals_1 = signal; },
+				3 => digits[signal as usize] = 7,
+				4 => { digits[signal as usize] = 4; signals_4 = signal; },
+				7 => digits[signal as usize] = 8,
+				6 => signals_len_6.push(signal),
+				5 => signals_len_5.push(signal),
+				n => return Err(format!("entry {}: a pattern can't have {} signals", idx, n)),
 			}
 		}
 
 		// I will use the difference operation between the signals to figure out
-		// different digits
+		// different digits. With bitmasks, a - b is a & !b
 
 		// Digits formed with 5 signals
-		let signals_4 = digits.get(&4).unwrap();
 		// Digit 2 is the only for which
 		// signals_digit_2 - signals_digit_4 = 3 signals
 		let signals_2_idx = signals_len_5.iter()
-			.position(|sig| sig.difference(&signals_4).count() == 3)
-			.unwrap();
+			.position(|sig| (sig & !signals_4).count_ones() == 3)
+			.ok_or_else(|| format!("entry {}: no pattern for the digit 2", idx))?;
 		let signals_2 = signals_len_5.swap_remove(signals_2_idx);
 
 		// Digit 3 is the only for which
 		// signals_digit_3 - signals_digit_2 = 1 signal
 		let signals_3_idx = signals_len_5.iter()
-			.position(|sig| sig.difference(&signals_2).count() == 1)
-			.unwrap();
+			.position(|sig| (sig & !signals_2).count_ones() == 1)
+			.ok_or_else(|| format!("entry {}: no pattern for the digit 3", idx))?;
 		let signals_3 = signals_len_5.swap_remove(signals_3_idx);
 
 		// The last element in the vector are the signals for the digit 5
-		let signals_5 = signals_len_5.swap_remove(0);
+		let signals_5 = signals_len_5.pop().ok_or_else(|| format!("entry {}: no pattern for the digit 5", idx))?;
 
 		// Digits formed with 6 signals
-		let signals_1 = digits.get(&1).unwrap();
    		// Digit 6 is the only for which
 		// signals_digit_6 - signals_digit_ 1 = 5 signals
 		let signals_6_idx = signals_len_6.iter()
-			.position( |sig| sig.difference(signals_1).count() == 5)
-			.unwrap();
+			.position( |sig| (sig & !signals_1).count_ones() == 5)
+			.ok_or_else(|| format!("entry {}: no pattern for the digit 6", idx))?;
 		let signals_6 = signals_len_6.swap_remove(signals_6_idx);
 
-		// 0 - 5 should get us a hashset with 2 element
-		// Digit 6 is the only for which
-		// signals_digit_6 - signals_digit_ 4 = 2 signals
+		// Digit 9 is the only for which
+		// signals_digit_9 - signals_digit_ 4 = 2 signals
 		let signals_9_idx = signals_len_6.iter()
-			.position(|sig| sig.difference(&signals_4).count() == 2)
-			.unwrap();
+			.position(|sig| (sig & !signals_4).count_ones() == 2)
+			.ok_or_else(|| format!("entry {}: no pattern for the digit 9", idx))?;
 		let signals_9 = signals_len_6.swap_remove(signals_9_idx);
 		// The last element in the vector are the signals for the digit 0
-		let signals_0 = signals_len_6.swap_remove(0);
+		let signals_0 = signals_len_6.pop().ok_or_else(|| format!("entry {}: no pattern for the digit 0", idx))?;
+
+		digits[signals_2 as usize] = 2;
+		digits[signals_3 as usize] = 3;
+		digits[signals_5 as usize] = 5;
+		digits[signals_6 as usize] = 6;
+		digits[signals_9 as usize] = 9;
+		digits[signals_0 as usize] = 0;
+
+		// Works for any number of output digits, every new digit shifts the
+		// previous ones a position to the left
+		let mut current_digit = 0_u128;
+
+		for digit_signals in &input.digits[idx] {
+
+			let digit = match digits[*digit_signals as usize] {
+				u8::MAX => return Err(format!("entry {}: output pattern {:07b} is not one of the signals", idx, digit_signals)),
+				digit => digit as u128,
+			};
+
+			current_digit = current_digit.checked_mul(10)
+				.and_then(|v| v.checked_add(digit))
+				.expect("display value overflowed u128");
+		}
+		digits_vector.push(current_digit);
+
+	}
+	return Ok(digits_vector.iter()
+		.fold(0_u128, |acc, value| acc.checked_add(*value).expect("sum overflowed u128")));
+
+}
+
+// Wire permutation solver
+// -----------------------
+// Instead of locating the digits one by one, we look for the actual
+// permutation wire -> segment. Every pattern (the ten signals and the
+// output digits) is a constraint: once mapped it must light the segments
+// of a glyph of the font. Segments and wires are stored as bits,
+// a = bit 0, b = bit 1...
+//
+// The standard seven segment display:
+//
+//   aaaa
+//  b    c
+//  b    c
+//   dddd
+//  e    f
+//  e    f
+//   gggg
+
+// Segments are named with letters, so a display has at most 26. The
+// decoder keeps its per wire state in arrays of this size instead of
+// allocating it for every entry.
+const MAX_SEGMENTS: usize = 26;
+
+// The glyphs a display can show, as the segments each one lights.
+// Glyphs must light different segments, otherwise they can't be told
+// apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentFont {
+	segments: usize,
+	glyphs: Vec<(char, u32)>,
+	// Glyphs grouped by the number of segments they light
+	by_length: Vec<Vec<u32>>,
+	// For every length: segments lit by any of the glyphs with that length,
+	// and segments lit by all of them
+	length_union: Vec<u32>,
+	length_intersection: Vec<u32>,
+	// Fonts of up to 7 segments (the puzzle ones) also get a lookup table
+	// segments -> symbol, empty for bigger fonts
+	symbols: Vec<Option<char>>,
+}
+
+impl SegmentFont {
+	// `glyphs` are (symbol, segments) pairs, the segments written as the
+	// letters of the segments they light, e.g. ('1', "cf")
+	pub fn new(segments: usize, glyphs: &[(char, &str)]) -> Result<Self, String> {
+		if segments == 0 || segments > MAX_SEGMENTS {
+			return Err(format!("a display needs between 1 and {} segments, not {}", MAX_SEGMENTS, segments));
+		}
+
+		let all_segments = ((1_u64 << segments) - 1) as u32;
+		let mut font = SegmentFont {
+			segments,
+			glyphs: Vec::new(),
+			by_length: vec![Vec::new(); segments + 1],
+			length_union: vec![0; segments + 1],
+			length_intersection: vec![all_segments; segments + 1],
+			symbols: if segments <= 7 { vec![None; 1 << segments] } else { Vec::new() },
+		};
+		for (symbol, pattern) in glyphs {
+			let mask = parse_wires(pattern, segments)?;
+			if let Some((other, _)) = font.glyphs.iter().find(|(_, m)| *m == mask) {
+				return Err(format!("glyphs '{}' and '{}' light the same segments", other, symbol));
+			}
+			let len = mask.count_ones() as usize;
+			font.glyphs.push((*symbol, mask));
+			font.by_length[len].push(mask);
+			font.length_union[len] |= mask;
+			font.length_intersection[len] &= mask;
+			if let Some(entry) = font.symbols.get_mut(mask as usize) {
+				*entry = Some(*symbol);
+			}
+		}
+		return Ok(font);
+	}
+
+	pub fn segments(&self) -> usize {
+		return self.segments;
+	}
+
+	// Segments lit by a symbol
+	pub fn glyph(&self, symbol: char) -> Option<u32> {
+		return self.glyphs.iter().find(|(s, _)| *s == symbol).map(|(_, m)| *m);
+	}
+
+	fn symbol(&self, segments: u32) -> Option<char> {
+		if !self.symbols.is_empty() {
+			return self.symbols[segments as usize];
+		}
+		return self.glyphs.iter().find(|(_, m)| *m == segments).map(|(s, _)| *s);
+	}
+
+	fn all_segments(&self) -> u32 {
+		return ((1_u64 << self.segments) - 1) as u32;
+	}
+
+	// Digits 0 to 9 in a seven segment display, the puzzle font
+	pub fn seven_segment() -> Self {
+		return SegmentFont::new(7, &[
+			('0', "abcefg"), ('1', "cf"), ('2', "acdeg"), ('3', "acdfg"), ('4', "bcdf"),
+			('5', "abdfg"), ('6', "abdefg"), ('7', "acf"), ('8', "abcdefg"), ('9', "abcdfg"),
+		]).unwrap();
+	}
+
+	// Seven segment digits plus the hexadecimal A b C d E F and a minus sign
+	pub fn seven_segment_hex() -> Self {
+		return SegmentFont::new(7, &[
+			('0', "abcefg"), ('1', "cf"), ('2', "acdeg"), ('3', "acdfg"), ('4', "bcdf"),
+			('5', "abdfg"), ('6', "abdefg"), ('7', "acf"), ('8', "abcdefg"), ('9', "abcdfg"),
+			('A', "abcdef"), ('B', "bdefg"), ('C', "abeg"), ('D', "cdefg"), ('E', "abdeg"),
+			('F', "abde"), ('-', "d"),
+		]).unwrap();
+	}
+
+	//  aaaa
+	// fi j kb
+	// f ijk b
+	//  gg hh
+	// e lmn c
+	// el m nc
+	//  dddd
+	pub fn fourteen_segment() -> Self {
+		return SegmentFont::new(14, &[
+			('0', "abcdefkl"), ('1', "bc"), ('2', "abdegh"), ('3', "abcdh"), ('4', "bcfgh"),
+			('5', "acdfgh"), ('6', "acdefgh"), ('7', "abc"), ('8', "abcdefgh"), ('9', "abcdfgh"),
+			('-', "gh"),
+		]).unwrap();
+	}
+
+	//  aa bb
+	// hi j kc
+	// h ijk c
+	//  pp ll
+	// g onm d
+	// go n md
+	//  ff ee
+	pub fn sixteen_segment() -> Self {
+		return SegmentFont::new(16, &[
+			('0', "abcdefghko"), ('1', "cd"), ('2', "abcefglp"), ('3', "abcdefl"), ('4', "cdhlp"),
+			('5', "abdefhlp"), ('6', "abdefghlp"), ('7', "abcd"), ('8', "abcdefghlp"), ('9', "abcdefhlp"),
+			('-', "lp"),
+		]).unwrap();
+	}
+
+	// Decodes a display entry: the signal patterns seen and the output
+	// patterns. Missing signal patterns are fine as long as the output can
+	// only be read one way.
+	pub fn decode(&self, signals: &[u32], outputs: &[u32]) -> Result<DecodedEntry, DecodeError> {
+		// The outputs are also patterns we have seen
+		let mut patterns : Vec<u32> = signals.iter().chain(outputs).copied().collect();
+		patterns.sort();
+		patterns.dedup();
+
+		if patterns.iter().any(|p| *p & !self.all_segments() != 0) {
+			return Err(DecodeError::Inconsistent);
+		}
+
+		let mut possible = [self.all_segments(); MAX_SEGMENTS];
+		let possible = &mut possible[..self.segments];
+		if !self.propagate_constraints(&patterns, possible) {
+			return Err(DecodeError::Inconsistent);
+		}
+
+		return self.find_mappings(&patterns, outputs, possible).into_result();
+	}
+
+	// possible[wire] = mask of the segments the wire can be connected to.
+	// Returns false if some wire ends up without options.
+	fn propagate_constraints(&self, patterns: &[u32], possible: &mut [u32]) -> bool {
+		// The glyphs a pattern can be only depend on how many wires are on
+		if patterns.iter().any(|p| self.by_length[p.count_ones() as usize].is_empty()) {
+			return false;
+		}
+
+		// The wires of a pattern light one of the candidate glyphs, so they
+		// go to segments of some candidate. The rest of the wires can't go to
+		// a segment every candidate lights. These masks don't depend on the
+		// other constraints, one pass is enough.
+		for pattern in patterns {
+			let len = pattern.count_ones() as usize;
+			let inside = self.length_union[len];
+			let outside = !self.length_intersection[len] & self.all_segments();
+			for (wire, options) in possible.iter_mut().enumerate() {
+				*options &= if pattern & (1 << wire) != 0 { inside } else { outside };
+			}
+		}
+
+		// A wire with a single option takes that segment from the others,
+		// which can leave more wires with a single option
+		loop {
+			if possible.contains(&0) {
+				return false;
+			}
+
+			let mut taken = 0;
+			for options in possible.iter().filter(|o| o.count_ones() == 1) {
+				if taken & options != 0 {
+					// Two wires can't go to the same segment
+					return false;
+				}
+				taken |= options;
+			}
+
+			let mut changed = false;
+			for options in possible.iter_mut().filter(|o| o.count_ones() > 1 && **o & taken != 0) {
+				*options &= !taken;
+				changed = true;
+			}
+			if !changed {
+				return true;
+			}
+		}
+	}
+
+	// Looks for the assignments allowed by `possible` that map every pattern
+	// to a glyph. Wires that are not part of any pattern are left
+	// unassigned, they don't change how the display is read.
+	// Wires that are in exactly the same patterns can be swapped without
+	// changing anything, so only the assignments where they go to
+	// increasing segments are tried, and they are reported as unknown.
+	fn find_mappings(&self, patterns: &[u32], outputs: &[u32], possible: &[u32]) -> SearchSummary {
+		// Wires that are part of the same patterns
+		let same_patterns = |a: usize, b: usize| -> bool {
+			return patterns.iter().all(|p| (p >> a) & 1 == (p >> b) & 1);
+		};
+
+		// When every wire has a single option (the usual case with the ten
+		// puzzle signals) no two of them can be in the same patterns, they
+		// would have the same options
+		let used_wires = patterns.iter().fold(0, |acc, p| acc | p);
+		let solved = possible.iter().all(|o| o.count_ones() == 1);
+		let mut wires = [0_usize; MAX_SEGMENTS];
+		let mut previous = [None; MAX_SEGMENTS];
+		let mut count = 0;
+		for wire in (0..self.segments).filter(|w| used_wires & (1 << w) != 0) {
+			wires[count] = wire;
+			if !solved {
+				previous[count] = wires[..count].iter().rev().copied().find(|other| same_patterns(*other, wire));
+			}
+			count += 1;
+		}
+		let (wires, previous) = (&wires[..count], &previous[..count]);
+
+		let search = MappingSearch { font: self, wires, previous, patterns, outputs, possible };
+		let mut summary = SearchSummary::default();
+		search.run(0, 0, &mut [None; MAX_SEGMENTS][..self.segments], &mut summary);
+
+		if let Some((_, mapping)) = &mut summary.first {
+			for (wire, other) in wires.iter().zip(previous) {
+				if let Some(other) = other {
+					mapping[*wire] = None;
+					mapping[*other] = None;
+				}
+			}
+		}
+		return summary;
+	}
+
+	// With part of the wires assigned, every pattern can still become a glyph
+	// of its length: the segments of its assigned wires are in the glyph, and
+	// the segments of the other assigned wires are not.
+	fn partial_mapping_valid(&self, mapping: &[Option<u8>], patterns: &[u32]) -> bool {
+		return patterns.iter().all(|pattern| {
+			let mut inside = 0_u32;
+			let mut outside = 0_u32;
+			for (wire, segment) in mapping.iter().enumerate() {
+				if let Some(segment) = segment {
+					if pattern & (1 << wire) != 0 { inside |= 1 << segment; } else { outside |= 1 << segment; }
+				}
+			}
+			return self.by_length[pattern.count_ones() as usize].iter()
+				.any(|glyph| glyph & inside == inside && glyph & outside == 0);
+		});
+	}
+}
+
+// Backtracking over the wires in `wires` order
+struct MappingSearch<'a> {
+	font: &'a SegmentFont,
+	wires: &'a [usize],
+	// Previous wire that is in the same patterns, if any
+	previous: &'a [Option<usize>],
+	patterns: &'a [u32],
+	outputs: &'a [u32],
+	possible: &'a [u32],
+}
 
-		digits.entry(2).or_insert(signals_2);
-		digits.entry(3).or_insert(signals_3);
-		digits.entry(5).or_insert(signals_5);
-		digits.entry(6).or_insert(signals_6);
-		digits.entry(9).or_insert(signals_9);
-		digits.entry(0).or_insert(signals_0);
+impl MappingSearch<'_> {
+	// Returns false when the search can stop
+	fn run(&self, idx: usize, used: u32, mapping: &mut [Option<u8>], summary: &mut SearchSummary) -> bool {
+		if idx == self.wires.len() {
+			// Every wire of the patterns is assigned, they must be glyphs now
+			if !self.patterns.iter().all(|p| self.font.symbol(apply_mapping(mapping, *p)).is_some()) {
+				return true;
+			}
+			let readout = self.outputs.iter()
+				.map(|o| self.font.symbol(apply_mapping(mapping, *o)).unwrap())
+				.collect();
+			return summary.add(mapping, readout);
+		}
 
-		let mut current_digit = 0_u32;
-		let mut current_exponent = 4_u32;
+		// Pruning is only worth it before trying several segments. Wires with
+		// a single option (all of them with the ten puzzle signals) are
+		// checked along with the next ones
+		let wire = self.wires[idx];
+		if (self.possible[wire] & !used).count_ones() > 1 && !self.font.partial_mapping_valid(mapping, self.patterns) {
+			return true;
+		}
+		let first_segment = self.previous[idx].map_or(0, |p| mapping[p].unwrap() as usize + 1);
+		let mut keep_going = true;
+		for segment in first_segment..self.font.segments {
+			let bit = 1 << segment;
+			if self.possible[wire] & bit != 0 && used & bit == 0 {
+				mapping[wire] = Some(segment as u8);
+				keep_going = self.run(idx + 1, used | bit, mapping, summary);
+				if !keep_going {
+					break;
+				}
+			}
+		}
+		mapping[wire] = None;
+		return keep_going;
+	}
+}
 
-		for digit_str in &input.digits[idx] {
+// What the search learns from the valid mappings, without keeping them
+// all: the readout of the first one and the wires all of them agree on, and
+// a second readout if some mapping reads the output differently
+#[derive(Default)]
+struct SearchSummary {
+	first: Option<(Vec<char>, Vec<Option<u8>>)>,
+	second: Option<Vec<char>>,
+}
 
-			let digit_signals : HashSet<char> = HashSet::from_iter(digit_str.chars());
+impl SearchSummary {
+	// Returns false once the readout is ambiguous, nothing else matters then
+	fn add(&mut self, mapping: &[Option<u8>], readout: Vec<char>) -> bool {
+		if self.first.is_none() {
+			self.first = Some((readout, mapping.to_vec()));
+			return true;
+		}
+		let (first_readout, known) = self.first.as_mut().unwrap();
+		if *first_readout != readout {
+			self.second = Some(readout);
+			return false;
+		}
+		for (known, segment) in known.iter_mut().zip(mapping) {
+			if *known != *segment {
+				*known = None;
+			}
+		}
+		return true;
+	}
+
+	fn into_result(self) -> Result<DecodedEntry, DecodeError> {
+		return match (self.first, self.second) {
+			(None, _) => Err(DecodeError::Inconsistent),
+			(Some((symbols, mapping)), None) => Ok(DecodedEntry { mapping, symbols }),
+			(Some((first, _)), Some(second)) => {
+				let mut readouts : Vec<String> = vec![first.iter().collect(), second.iter().collect()];
+				readouts.sort();
+				Err(DecodeError::Ambiguous(readouts))
+			}
+		};
+	}
+}
 
-			let digit = digits.iter()
-				.find_map(|(k, v)| if &digit_signals == v {Some(*k)} else { None })
-				.unwrap() as u32;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+	// No wire permutation explains all the patterns
+	Inconsistent,
+	// Several permutations fit the patterns, and they decode the output to
+	// different readouts. The search stops at the second one, so these are
+	// two of the possible readouts
+	Ambiguous(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedEntry {
+	// Segment each wire is connected to, mapping[0] is the segment of wire
+	// 'a'. None when the patterns are not enough to know it (some of them
+	// were missing), but the output could be decoded anyway
+	pub mapping: Vec<Option<u8>>,
+	// Output symbols, as many as the display has
+	pub symbols: Vec<char>,
+}
 
-			current_exponent -= 1_u32;
-			let v =  digit * 10_u32.pow(current_exponent as u32);
-			current_digit += v;
+impl DecodedEntry {
+	// Symbols as shown in the display, leading zeros included
+	pub fn readout(&self) -> String {
+		return self.symbols.iter().collect();
+	}
+
+	// Numeric value of a readout of decimal digits. None if it has other
+	// symbols or doesn't fit (more than 38 digits)
+	pub fn value(&self) -> Option<u128> {
+		return self.symbols.iter()
+			.try_fold(0_u128, |acc, s| acc.checked_mul(10)?.checked_add(s.to_digit(10)? as u128));
+	}
+
+	// Numeric value, None if it doesn't fit (more than 19 digits)
+	pub fn value_u64(&self) -> Option<u64> {
+		return self.value().and_then(|v| u64::try_from(v).ok());
+	}
+}
+
+// Wires written as letters, a = bit 0
+pub fn parse_wires(pattern: &str, wires: usize) -> Result<u32, String> {
+	let mut mask = 0_u32;
+	for c in pattern.chars() {
+		let wire = (c as u32).wrapping_sub('a' as u32);
+		if wire >= wires as u32 {
+			return Err(format!("invalid pattern '{}'", pattern));
+		}
+		mask |= 1 << wire;
+	}
+	return Ok(mask);
+}
+
+// Segments lit by a pattern of wires with the given (maybe partial) mapping
+fn apply_mapping(mapping: &[Option<u8>], wires: u32) -> u32 {
+	let mut segments = 0;
+	let mut remaining = wires;
+	while remaining != 0 {
+		if let Some(segment) = mapping[remaining.trailing_zeros() as usize] {
+			segments |= 1 << segment;
 		}
-		digits_vector.push(current_digit as u32);
+		// Clears the lowest wire
+		remaining &= remaining - 1;
+	}
+	return segments;
+}
+
+// Decodes a puzzle entry, with the standard seven segment digits. The font
+// is built once and shared by all the entries
+pub fn decode_entry(signals: &[u8], outputs: &[u8]) -> Result<DecodedEntry, DecodeError> {
+	static FONT: std::sync::OnceLock<SegmentFont> = std::sync::OnceLock::new();
+	let font = FONT.get_or_init(SegmentFont::seven_segment);
+
+	let patterns : Vec<u32> = signals.iter().chain(outputs).map(|p| *p as u32).collect();
+	let (signals, outputs) = patterns.split_at(signals.len());
+	return font.decode(signals, outputs);
+}
 
+// Test data generator
+// -------------------
+// Makes puzzle lines (`patterns | outputs`) for random displays: every
+// entry gets its own wire permutation and a random value with
+// `digits_per_entry` digits (leading zeros included). The patterns and the
+// letters inside them are shuffled like in the real input.
+// Returns the lines and the value each one shows.
+pub fn generate_input(seed: u64, entries: usize, digits_per_entry: usize) -> (String, Vec<u128>) {
+	assert!((1..=38).contains(&digits_per_entry), "values must have between 1 and 38 digits");
+
+	let font = SegmentFont::seven_segment();
+	let digit_segments : Vec<u32> = (0..10).map(|d| font.glyph(char::from(b'0' + d)).unwrap()).collect();
+
+	let mut rng = Rng::new(seed);
+	let mut lines = Vec::with_capacity(entries);
+	let mut values = Vec::with_capacity(entries);
+
+	for _ in 0..entries {
+		// permutation[segment] = wire connected to that segment
+		let mut permutation : Vec<u8> = (0..7).collect();
+		rng.shuffle(&mut permutation);
+
+		let mut signals = digit_segments.clone();
+		rng.shuffle(&mut signals);
+
+		let digits : Vec<usize> = (0..digits_per_entry).map(|_| rng.gen_range(0, 10) as usize).collect();
+		let value = digits.iter().fold(0_u128, |acc, d| acc * 10 + *d as u128);
+
+		let mut scramble = |segments: u32| -> String {
+			let mut wires : Vec<char> = (0..7)
+				.filter(|segment| segments & (1 << segment) != 0)
+				.map(|segment| (b'a' + permutation[segment]) as char)
+				.collect();
+			rng.shuffle(&mut wires);
+			return wires.into_iter().collect();
+		};
+
+		let signals : Vec<String> = signals.iter().map(|s| scramble(*s)).collect();
+		let outputs : Vec<String> = digits.iter().map(|d| scramble(digit_segments[*d])).collect();
+
+		lines.push(format!("{} | {}", signals.join(" "), outputs.join(" ")));
+		values.push(value);
 	}
-	return digits_vector.iter().sum();
 
+	return (lines.join("\n"), values);
 }
 
+#[cfg(test)]
 mod tests {
 	use super::*;
 
@@ -177,4 +696,200 @@ gcafb gcf dcaebfg ecagb gf abcdeg gaef cafbge fdbac fegbdc |fgae cfgab fg bagce"
 		assert_eq!(result, 61229);
 	}
 
+	#[test]
+	fn test_day8_part2_set_difference() {
+		let input = parser(INPUT_LITERAL);
+		let result = solve_part2_set_difference(&input);
+		assert_eq!(result, Ok(61229));
+	}
+
+	#[test]
+	fn test_day8_set_difference_errors() {
+		// "bc" is not one of the signals
+		let input = parser("be cfbegad cbdgef fgaecd cgeb fdcge agebfd fecdb fabcd edb | fdgacbe bc");
+		assert!(solve_part2_set_difference(&input).is_err());
+
+		// Without the 3 the 5 can't be told apart
+		let input = parser("be cfbegad cbdgef fgaecd cgeb agebfd fecdb fabcd edb | fdgacbe");
+		assert!(solve_part2_set_difference(&input).is_err());
+	}
+
+	fn split(patterns: &str) -> Vec<u8> {
+		return patterns.split_whitespace().map(|p| parse_pattern(p).unwrap()).collect();
+	}
+
+	#[test]
+	fn test_day8_decode_entry() {
+		let signals = split("acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab");
+		let outputs = split("cdfeb fcadb cdfeb cdbaf");
+
+		let decoded = decode_entry(&signals, &outputs).unwrap();
+		assert_eq!(decoded.value(), Some(5353));
+		assert_eq!(decoded.symbols, vec!['5', '3', '5', '3']);
+		// From the puzzle description: d -> a, e -> b, a -> c, f -> d, g -> e,
+		// b -> f, c -> g
+		assert_eq!(decoded.mapping, vec![Some(2), Some(5), Some(6), Some(0), Some(1), Some(3), Some(4)]);
+	}
+
+	#[test]
+	fn test_day8_decode_missing_patterns() {
+		// Without the 1, 7 and 4 patterns the output is still readable
+		let signals = split("acedgfb cdfbe gcdfa fbcad cefabd cdfgeb cagedb");
+		let outputs = split("cdfeb fcadb cdfeb cdbaf");
+		assert_eq!(decode_entry(&signals, &outputs).unwrap().value(), Some(5353));
+
+		// Only the output, and it is an 8: any permutation works
+		let decoded = decode_entry(&[], &split("abcdefg")).unwrap();
+		assert_eq!(decoded.value(), Some(8));
+		assert_eq!(decoded.mapping, vec![None; 7]);
+	}
+
+	#[test]
+	fn test_day8_decode_errors() {
+		// Alone, a pattern of 5 segments could be a 2, 3 or 5
+		// The search stops at the second readout it finds
+		match decode_entry(&[], &split("abcde")) {
+			Err(DecodeError::Ambiguous(readouts)) => {
+				assert_eq!(readouts.len(), 2);
+				assert!(readouts[0] < readouts[1]);
+				assert!(readouts.iter().all(|r| ["2", "3", "5"].contains(&r.as_str())));
+			},
+			result => panic!("expected an ambiguous readout, got {:?}", result),
+		}
+
+		// But knowing which wires are the 1, it can only be a 3
+		assert_eq!(decode_entry(&split("ab"), &split("abcde")).unwrap().value(), Some(3));
+
+		// Two different patterns of two segments, there is only one 1
+		assert_eq!(decode_entry(&split("ab cd"), &split("ab")), Err(DecodeError::Inconsistent));
+	}
+
+	#[test]
+	fn test_day8_parse_pattern() {
+		assert_eq!(parse_pattern("ab"), Ok(0b11));
+		assert_eq!(parse_pattern("gfedcba"), Ok(0b111_1111));
+		assert!(parse_pattern("xy").is_err());
+	}
+
+	#[test]
+	fn test_day8_long_readouts() {
+		// Same wiring as the puzzle description example
+		let signals = "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab";
+		let outputs = "cagedb ab gcdfa fbcad eafb cdfbe cdfgeb dab acedgfb cefabd ab ab";
+
+		let decoded = decode_entry(&split(signals), &split(outputs)).unwrap();
+		assert_eq!(decoded.readout(), "012345678911");
+		assert_eq!(decoded.value(), Some(12345678911));
+		assert_eq!(decoded.value_u64(), Some(12345678911));
+
+		// Works the same for every solver
+		let input = parser(&format!("{} | {}\n{} | {}", signals, outputs, signals, outputs));
+		assert_eq!(solve_part2(&input), 2 * 12345678911);
+		assert_eq!(solve_part2_set_difference(&input), Ok(2 * 12345678911));
+
+		// 40 eights don't fit in any integer
+		let outputs = vec!["acedgfb"; 40].join(" ");
+		let decoded = decode_entry(&split(signals), &split(&outputs)).unwrap();
+		assert_eq!(decoded.readout(), "8".repeat(40));
+		assert_eq!(decoded.value(), None);
+
+		let outputs = vec!["acedgfb"; 20].join(" ");
+		let decoded = decode_entry(&split(signals), &split(&outputs)).unwrap();
+		assert_eq!(decoded.value(), Some(88888888888888888888));
+		assert_eq!(decoded.value_u64(), None);
+	}
+
+	// Patterns shown by a display wired with `permutation` (wire -> segment)
+	fn scramble(font: &SegmentFont, permutation: &[u8], symbols: &str) -> Vec<u32> {
+		return symbols.chars()
+			.map(|symbol| {
+				let glyph = font.glyph(symbol).unwrap();
+				(0..permutation.len())
+					.filter(|wire| glyph & (1 << permutation[*wire]) != 0)
+					.fold(0, |acc, wire| acc | (1 << wire))
+			})
+			.collect();
+	}
+
+	#[test]
+	fn test_day8_font_validation() {
+		assert!(SegmentFont::new(7, &[('1', "cf"), ('l', "fc")]).is_err());
+		assert!(SegmentFont::new(7, &[('1', "cz")]).is_err());
+		assert!(SegmentFont::new(0, &[]).is_err());
+		assert_eq!(SegmentFont::seven_segment_hex().glyph('-'), Some(0b000_1000));
+	}
+
+	#[test]
+	fn test_day8_hex_font() {
+		let font = SegmentFont::seven_segment_hex();
+		let permutation = [3, 6, 0, 5, 1, 4, 2];
+
+		let signals = scramble(&font, &permutation, "0123456789ABCDEF-");
+		let outputs = scramble(&font, &permutation, "-C0FFEE");
+		let decoded = font.decode(&signals, &outputs).unwrap();
+		assert_eq!(decoded.readout(), "-C0FFEE");
+		assert_eq!(decoded.value(), None);
+		let expected : Vec<Option<u8>> = permutation.iter().map(|s| Some(*s)).collect();
+		assert_eq!(decoded.mapping, expected);
+
+		// Decimal digits only, still readable as hex
+		let signals = scramble(&font, &permutation, "0123456789");
+		let outputs = scramble(&font, &permutation, "2021");
+		assert_eq!(font.decode(&signals, &outputs).unwrap().value(), Some(2021));
+	}
+
+	#[test]
+	fn test_day8_fourteen_and_sixteen_segments() {
+		let font = SegmentFont::fourteen_segment();
+		let permutation = [13, 2, 7, 0, 11, 4, 9, 1, 12, 3, 10, 5, 8, 6];
+		let signals = scramble(&font, &permutation, "0123456789-");
+		let outputs = scramble(&font, &permutation, "-9876543210");
+		let decoded = font.decode(&signals, &outputs).unwrap();
+		assert_eq!(decoded.readout(), "-9876543210");
+		// The segments the font never uses can't be located, and neither can
+		// the two diagonals of the zero, they are always lit together
+		assert_eq!(decoded.mapping.iter().filter(|m| m.is_none()).count(), 6);
+
+		let font = SegmentFont::sixteen_segment();
+		let permutation = [15, 0, 14, 1, 13, 2, 12, 3, 11, 4, 10, 5, 9, 6, 8, 7];
+		let signals = scramble(&font, &permutation, "0123456789-");
+		let outputs = scramble(&font, &permutation, "31415926");
+		let decoded = font.decode(&signals, &outputs).unwrap();
+		assert_eq!(decoded.value(), Some(31415926));
+	}
+
+	#[test]
+	fn test_day8_generated_round_trip() {
+		for seed in 0..20 {
+			let digits = 1 + seed as usize % 12;
+			let (text, values) = generate_input(seed, 25, digits);
+			assert_eq!(text, generate_input(seed, 25, digits).0);
+
+			let input = parser(&text);
+			assert_eq!(input.signals.len(), 25);
+			assert!(input.digits.iter().all(|d| d.len() == digits));
+			assert_eq!(solve_part2(&input), values.iter().sum::<u128>());
+			assert_eq!(solve_part2_set_difference(&input), Ok(values.iter().sum::<u128>()));
+
+			for (line, value) in text.lines().zip(&values) {
+				let mut parts = line.split('|');
+				let decoded = decode_entry(&split(parts.next().unwrap()), &split(parts.next().unwrap())).unwrap();
+				assert_eq!(decoded.value(), Some(*value));
+			}
+		}
+	}
+
+	#[test]
+	fn test_day8_large_font_underdetermined() {
+		// A single 3 in sixteen segments (the only glyph with 7 segments):
+		// every permutation of its wires works, none of them can be located
+		let font = SegmentFont::sixteen_segment();
+		let decoded = font.decode(&[], &[font.glyph('3').unwrap()]).unwrap();
+		assert_eq!(decoded.readout(), "3");
+		assert!(decoded.mapping.iter().all(|m| m.is_none()));
+
+		// The 0 and the 8 both have 10 segments
+		let result = font.decode(&[], &[font.glyph('8').unwrap()]);
+		assert_eq!(result, Err(DecodeError::Ambiguous(vec!["0".to_string(), "8".to_string()])));
+	}
 }
@@ -1,7 +1,7 @@
 use aoc_runner_derive::aoc;
 use aoc_runner_derive::aoc_generator;
-use std::collections::VecDeque;
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::fmt;
 
 // https://adventofcode.com/2021/day/9
 
@@ -23,97 +23,720 @@ pub fn parser(input: &str) -> Input{
 	};
 }
 
+impl Input {
+	pub fn matrix(&self) -> &Vec<Vec<u8>> {
+		return &self.matrix;
+	}
+}
+
+// PGM heightmaps
+// --------------
+// Grayscale images from the survey tools, ASCII (P2) or binary (P5).
+// http://netpbm.sourceforge.net/doc/pgm.html
+// The gray values (0 to maxval) are quantized to `levels` heights, so with
+// 10 levels the map is like the puzzle input, 9 being the walls.
+pub fn parse_pgm(data: &[u8], levels: u16) -> Result<Input, String> {
+	if !(2..=256).contains(&levels) {
+		return Err(format!("levels must be between 2 and 256, not {}", levels));
+	}
+
+	let mut pos = 0;
+	let magic = next_pgm_token(data, &mut pos)?;
+	if magic != "P2" && magic != "P5" {
+		return Err(format!("not a PGM file, magic number '{}'", magic));
+	}
+
+	let mut header = [0_u32; 3];
+	for (value, name) in header.iter_mut().zip(["width", "height", "maxval"]) {
+		let token = next_pgm_token(data, &mut pos)?;
+		*value = token.parse().map_err(|_| format!("invalid {} '{}'", name, token))?;
+	}
+	let [width, height, maxval] = header;
+	if width == 0 || height == 0 {
+		return Err("empty image".to_string());
+	}
+	if maxval == 0 || maxval > 65535 {
+		return Err(format!("invalid maxval {}", maxval));
+	}
+
+	// The header can't be trusted, so nothing is allocated from it: the P2
+	// pixels are pushed as they are read and the P5 raster must be there
+	let count = (width as usize).checked_mul(height as usize).ok_or("image is too large")?;
+	let mut pixels = Vec::new();
+	if magic == "P2" {
+		for _ in 0..count {
+			let token = next_pgm_token(data, &mut pos)?;
+			pixels.push(token.parse::<u32>().map_err(|_| format!("invalid pixel '{}'", token))?);
+		}
+	}
+	else {
+		// A single whitespace after maxval, then the raster, big endian when
+		// a pixel takes two bytes
+		pos += 1;
+		let bytes = if maxval < 256 { 1 } else { 2 };
+		let size = count.checked_mul(bytes).ok_or("image is too large")?;
+		if data.len().saturating_sub(pos) < size {
+			return Err("image data is too short".to_string());
+		}
+		let raster = &data[pos..pos + size];
+		pixels.extend(raster.chunks(bytes).map(|c| c.iter().fold(0_u32, |acc, b| acc << 8 | *b as u32)));
+	}
+
+	if let Some(pixel) = pixels.iter().find(|p| **p > maxval) {
+		return Err(format!("pixel {} is above maxval {}", pixel, maxval));
+	}
+
+	let matrix = pixels.chunks(width as usize)
+		.map(|row| row.iter().map(|p| (*p as u64 * levels as u64 / (maxval as u64 + 1)) as u8).collect())
+		.collect();
+	return Ok(Input { matrix });
+}
+
+// Header and P2 tokens are separated by whitespace, comments go from '#'
+// to the end of the line
+fn next_pgm_token<'a>(data: &'a [u8], pos: &mut usize) -> Result<&'a str, String> {
+	loop {
+		match data.get(*pos) {
+			Some(b'#') => {
+				while data.get(*pos).is_some_and(|c| *c != b'\n') {
+					*pos += 1;
+				}
+			},
+			Some(c) if c.is_ascii_whitespace() => *pos += 1,
+			Some(_) => break,
+			None => return Err("unexpected end of file".to_string())
+		}
+	}
+
+	let start = *pos;
+	while data.get(*pos).is_some_and(|c| !c.is_ascii_whitespace()) {
+		*pos += 1;
+	}
+	return std::str::from_utf8(&data[start..*pos]).map_err(|_| "invalid header".to_string());
+}
+
+pub fn load_pgm(path: &str, levels: u16) -> Result<Input, String> {
+	let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+	return parse_pgm(&data, levels);
+}
+
 #[aoc(day9, part1)]
 pub fn solve_part1(input: &Input) -> u32 {
-	let coords = find_lowest_points_coords(&input.matrix);
-	return coords.iter()
-		.map(|c| input.matrix[c.0 as usize][c.1 as usize] as u32 + 1)
+	// Low regions made of walls (a map of 9s) are not low points
+	let basins = label_basins(&input.matrix);
+	return basins.low_regions.iter()
+		.filter(|region| region.basin.is_some())
+		.map(|region| region.risk)
 		.sum();
 }
 
 #[aoc(day9, part2)]
 pub fn solve_part2(input: &Input) -> u32 {
-	let coords = find_lowest_points_coords(&input.matrix);
-	let mut basins = find_all_basins_sizes(&coords, &input.matrix);
-	basins.sort();
-	basins.reverse();
-	let result = basins[0..3].iter().fold(1, |acc, b| acc * b);
+	let basins = label_basins(&input.matrix);
+	let mut sizes = basins.sizes.clone();
+	sizes.sort();
+	sizes.reverse();
+	let result = sizes[0..3].iter().fold(1, |acc, b| acc * b);
 	return result;
 }
 
-fn find_all_basins_sizes(lowest_points: &Vec<Coord>, matrix: &Vec<Vec<u8>>) -> Vec<u32> {
-	return lowest_points.iter()
-		.map(|p| first_bread_search(p, matrix).len() as u32)
-		.collect();
+// Disjoint sets over the cells of the matrix (index = row * width + col),
+// with path halving and union by size
+struct UnionFind {
+	parent: Vec<usize>,
+	size: Vec<usize>
 }
 
-fn first_bread_search(initial_coord: &Coord, matrix: &Vec<Vec<u8>>) -> Vec<Coord> {
-	let height = matrix.len() as u32;
-	let width = matrix[0].len() as u32;
+impl UnionFind {
+	fn new(len: usize) -> Self {
+		return UnionFind {
+			parent: (0..len).collect(),
+			size: vec![1; len]
+		};
+	}
 
-	let mut frontier = VecDeque::<Coord>::new();
-	let mut result = HashSet::<Coord>::new();
-	result.insert(*initial_coord);
-	frontier.push_back(*initial_coord);
+	fn find(&mut self, mut idx: usize) -> usize {
+		while self.parent[idx] != idx {
+			self.parent[idx] = self.parent[self.parent[idx]];
+			idx = self.parent[idx];
+		}
+		return idx;
+	}
 
-	// I use a breadth-first search
-	// https://www.redblobgames.com/pathfinding/a-star/introduction.html#breadth-first-search
-	while !frontier.is_empty() {
-		let current_coord = frontier.pop_front().unwrap();
+	fn union(&mut self, a: usize, b: usize) {
+		let mut a = self.find(a);
+		let mut b = self.find(b);
+		if a == b {
+			return;
+		}
+		if self.size[a] < self.size[b] {
+			std::mem::swap(&mut a, &mut b);
+		}
+		self.parent[b] = a;
+		self.size[a] += self.size[b];
+	}
+}
 
-		let neighbourhoods = get_neighbourhoods(&current_coord , width, height);
-		for n in neighbourhoods{
-			let n_val = matrix[n.0 as usize][n.1 as usize];
+// A set of connected cells with the same height, all the cells around it
+// being higher. A single cell lower than its neighbours is the usual case,
+// but flat bottoms are a low region too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LowRegion {
+	pub cells: Vec<Coord>,
+	pub height: u8,
+	// Basin the region is in, None if the region is made of walls
+	pub basin: Option<usize>,
+	pub risk: u32
+}
 
-			if n_val != 9 {
-				if !result.contains(&n) {
-					result.insert(n);
-					frontier.push_back(n)
-				};
+// The puzzle risk: a low region is a single low point, no matter how big
+// it is
+pub fn puzzle_risk(region: &LowRegion) -> u32 {
+	return region.height as u32 + 1;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+	// Up, down, left and right
+	Four,
+	// Diagonals too
+	Eight
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeightmapOptions {
+	pub connectivity: Connectivity,
+	// Cells this high or higher are walls between basins
+	pub wall_height: u8,
+	pub risk: fn(&LowRegion) -> u32
+}
+
+impl Default for HeightmapOptions {
+	// The puzzle rules
+	fn default() -> Self {
+		return HeightmapOptions {
+			connectivity: Connectivity::Four,
+			wall_height: 9,
+			risk: puzzle_risk
+		};
+	}
+}
+
+impl HeightmapOptions {
+	pub fn is_wall(&self, value: u8) -> bool {
+		return value >= self.wall_height;
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasinMap {
+	// labels[row][col] = basin of the cell, None for the walls
+	pub labels: Vec<Vec<Option<usize>>>,
+	// Number of cells of every basin
+	pub sizes: Vec<u32>,
+	pub low_regions: Vec<LowRegion>
+}
+
+pub fn label_basins(matrix: &Vec<Vec<u8>>) -> BasinMap {
+	return label_basins_with(matrix, &HeightmapOptions::default());
+}
+
+// Labels the basins (areas of cells that are not walls) and finds the low
+// regions. A first pass joins every cell to the neighbours after it (below,
+// to the right, and the lower diagonals with eight connectivity) in two
+// union-find structures: one for the basins and one for the plateaus
+// (neighbours with the same height). Then the basins are labeled, the
+// plateaus with a lower neighbour are discarded, and the remaining plateaus
+// are collected as low regions, one pass over the matrix each.
+pub fn label_basins_with(matrix: &Vec<Vec<u8>>, options: &HeightmapOptions) -> BasinMap {
+	let height = matrix.len();
+	let width = if height > 0 { matrix[0].len() } else { 0 };
+	let idx = |row: usize, col: usize| row * width + col;
+	let is_wall = |value: u8| options.is_wall(value);
+
+	let mut forward = vec![(1, 0), (0, 1)];
+	if options.connectivity == Connectivity::Eight {
+		forward.extend([(1, 1), (1, -1)]);
+	}
+
+	let mut basins = UnionFind::new(width * height);
+	let mut plateaus = UnionFind::new(width * height);
+
+	for row in 0..height {
+		for col in 0..width {
+			let value = matrix[row][col];
+			for (d_row, d_col) in &forward {
+				let n_row = row + d_row;
+				let n_col = col as i32 + d_col;
+				if n_row >= height || n_col < 0 || n_col as usize >= width {
+					continue;
+				}
+				let n_col = n_col as usize;
+				let n_value = matrix[n_row][n_col];
+				if !is_wall(value) && !is_wall(n_value) {
+					basins.union(idx(row, col), idx(n_row, n_col));
+				}
+				if value == n_value {
+					plateaus.union(idx(row, col), idx(n_row, n_col));
+				}
+			}
+		}
+	}
+
+	// Basin labels in order of appearance
+	let mut labels = vec![vec![None; width]; height];
+	let mut sizes = Vec::new();
+	let mut label_of_root = vec![None; width * height];
+	for row in 0..height {
+		for col in 0..width {
+			if is_wall(matrix[row][col]) {
+				continue;
 			}
+			let root = basins.find(idx(row, col));
+			let label = *label_of_root[root].get_or_insert_with(|| {
+				sizes.push(0);
+				sizes.len() - 1
+			});
+			sizes[label] += 1;
+			labels[row][col] = Some(label);
 		}
 	}
 
-	return result.into_iter().collect();
+	// A plateau is a low region when no cell around it is lower or equal,
+	// equal neighbours would be part of the plateau
+	let mut is_low = vec![true; width * height];
+	for row in 0..height {
+		for col in 0..width {
+			let root = plateaus.find(idx(row, col));
+			let coord = (row as u32, col as u32);
+			if get_neighbourhoods(&coord, width as u32, height as u32, options.connectivity).iter()
+				.any(|n| matrix[n.0 as usize][n.1 as usize] < matrix[row][col]) {
+				is_low[root] = false;
+			}
+		}
+	}
+
+	let mut low_regions : Vec<LowRegion> = Vec::new();
+	let mut region_of_root = vec![None; width * height];
+	for row in 0..height {
+		for col in 0..width {
+			let root = plateaus.find(idx(row, col));
+			if !is_low[root] {
+				continue;
+			}
+			let region = *region_of_root[root].get_or_insert_with(|| {
+				low_regions.push(LowRegion { cells: Vec::new(), height: matrix[row][col], basin: labels[row][col], risk: 0 });
+				low_regions.len() - 1
+			});
+			low_regions[region].cells.push((row as u32, col as u32));
+		}
+	}
+
+	for region in low_regions.iter_mut() {
+		region.risk = (options.risk)(region);
+	}
+
+	return BasinMap { labels, sizes, low_regions };
 }
 
-fn find_lowest_points_coords(matrix: &Vec<Vec<u8>>) -> Vec<Coord> {
-	let height = matrix.len() as u32;
-	let width = matrix[0].len() as u32;
-	let mut result : Vec<Coord> = Vec::new();
+// Watershed segmentation
+// ----------------------
+// Basins split at the walls are a simplification: water doesn't stop at a
+// 9, it flows down. Here every cell drains to a low region following the
+// steepest descent: it flows to its lowest neighbour, or on ties to the
+// first one in (row, col) order. Cells on a plateau that is not a low
+// region don't have a lower neighbour, so the whole plateau flows out
+// through its steepest exit.
 
-	for (row, rowline) in matrix.iter().enumerate() {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watershed {
+	// labels[row][col] = low region (index in low_regions) the cell drains to
+	pub labels: Vec<Vec<usize>>,
+	pub low_regions: Vec<LowRegion>,
+	// Number of cells draining to every low region
+	pub sizes: Vec<u32>,
+	// Cells with lower neighbours that drain to different low regions, so
+	// the water could go either way
+	pub divides: Vec<Coord>
+}
+
+impl Watershed {
+	// Cells that aren't walls and drain to a low region outside the wall
+	// basin they are in. On inputs where the walls really separate the
+	// basins (like the puzzle) there are none.
+	pub fn disagreements(&self, basins: &BasinMap) -> Vec<Coord> {
+		let mut result = Vec::new();
+		for (row, labels) in basins.labels.iter().enumerate() {
+			for (col, label) in labels.iter().enumerate() {
+				if label.is_some() && self.low_regions[self.labels[row][col]].basin != *label {
+					result.push((row as u32, col as u32));
+				}
+			}
+		}
+		return result;
+	}
+}
+
+pub fn watershed(matrix: &Vec<Vec<u8>>, options: &HeightmapOptions) -> Watershed {
+	let height = matrix.len();
+	let width = if height > 0 { matrix[0].len() } else { 0 };
+	let idx = |coord: &Coord| coord.0 as usize * width + coord.1 as usize;
+	let value = |coord: &Coord| matrix[coord.0 as usize][coord.1 as usize];
+	let neighbours = |coord: &Coord| get_neighbourhoods(coord, width as u32, height as u32, options.connectivity);
 
-		for (col, _) in rowline.iter().enumerate() {
-			let current_coord = (row as u32, col as u32);
-			let coords = get_neighbourhoods(&current_coord, width, height);
-			let value_to_check = matrix[row][col];
+	// Where the water goes from a cell, if it has lower neighbours
+	let steepest = |coord: &Coord| -> Option<Coord> {
+		return neighbours(coord).into_iter()
+			.filter(|n| value(n) < value(coord))
+			.min_by_key(|n| (value(n), *n));
+	};
 
-			let min_surrounding_value = coords.iter()
-				.map(|c| matrix[c.0 as usize][c.1 as usize])
-				.fold(std::u8::MAX, |acc, v| if v < acc { return v } else { return acc } );
+	let low_regions = label_basins_with(matrix, options).low_regions;
 
-			if value_to_check < min_surrounding_value {
-				result.push(current_coord);
+	let mut plateaus = UnionFind::new(width * height);
+	let cells : Vec<Coord> = (0..height as u32).flat_map(|row| (0..width as u32).map(move |col| (row, col))).collect();
+	for cell in &cells {
+		for n in neighbours(cell) {
+			if value(&n) == value(cell) {
+				plateaus.union(idx(cell), idx(&n));
 			}
 		}
 	}
 
+	let mut members : Vec<Vec<Coord>> = vec![Vec::new(); width * height];
+	for cell in &cells {
+		members[plateaus.find(idx(cell))].push(*cell);
+	}
+
+	// Lower plateaus first, so the cells below have a label already
+	let mut order : Vec<usize> = (0..width * height).filter(|root| !members[*root].is_empty()).collect();
+	order.sort_by_key(|root| value(&members[*root][0]));
+
+	let mut labels = vec![vec![usize::MAX; width]; height];
+	for (region_idx, region) in low_regions.iter().enumerate() {
+		for cell in &region.cells {
+			labels[cell.0 as usize][cell.1 as usize] = region_idx;
+		}
+	}
+
+	for root in order {
+		let plateau = &members[root];
+		if labels[plateau[0].0 as usize][plateau[0].1 as usize] != usize::MAX {
+			// Low region
+			continue;
+		}
+
+		let exit = plateau.iter()
+			.filter_map(steepest)
+			.min_by_key(|n| (value(n), *n))
+			.expect("a plateau that is not a low region has a lower neighbour");
+		let plateau_label = labels[exit.0 as usize][exit.1 as usize];
+
+		for cell in plateau {
+			labels[cell.0 as usize][cell.1 as usize] = match steepest(cell) {
+				Some(n) => labels[n.0 as usize][n.1 as usize],
+				None => plateau_label
+			};
+		}
+	}
+
+	let mut sizes = vec![0; low_regions.len()];
+	let mut divides = Vec::new();
+	for cell in &cells {
+		sizes[labels[cell.0 as usize][cell.1 as usize]] += 1;
+
+		let mut below : Vec<usize> = neighbours(cell).iter()
+			.filter(|n| value(n) < value(cell))
+			.map(|n| labels[n.0 as usize][n.1 as usize])
+			.collect();
+		below.sort();
+		below.dedup();
+		if below.len() > 1 {
+			divides.push(*cell);
+		}
+	}
+
+	return Watershed { labels, low_regions, sizes, divides };
+}
+
+// Merge tree
+// ----------
+// Flooding the map: at water level L every cell with height <= L is under
+// water, and the connected areas of flooded cells are the basins. When the
+// water rises basins grow, new ones appear at their lowest cell, and some
+// of them join. When basins join the oldest one (lowest birth level, then
+// lowest id) keeps going and the others die, like in persistent homology.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FloodBasin {
+	pub id: usize,
+	// Level the basin appears at, the height of its low point
+	pub birth: u8,
+	pub low_point: Coord,
+	// Level it joins an older basin, and the basin it joins
+	pub death: Option<u8>,
+	pub merged_into: Option<usize>
+}
+
+impl FloodBasin {
+	// Levels the basin lives on its own, None if it never merges
+	pub fn persistence(&self) -> Option<u8> {
+		return self.death.map(|death| death - self.birth);
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FloodLevel {
+	pub level: u8,
+	// (basin, size) of the basins at this level
+	pub basins: Vec<(usize, u32)>,
+	// (basin, basin it merges into) for the basins that die at this level
+	pub merges: Vec<(usize, usize)>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeTree {
+	pub basins: Vec<FloodBasin>,
+	// One per level, from 0 to the highest cell (9 at least)
+	pub levels: Vec<FloodLevel>,
+	// (basin, level) each cell joined when it got flooded
+	flooded: Vec<Vec<(usize, u8)>>
+}
+
+impl MergeTree {
+	pub fn level(&self, level: u8) -> Option<&FloodLevel> {
+		return self.levels.get(level as usize);
+	}
+
+	// Basin a cell is part of at a given level, None if it is still dry
+	pub fn basin_at(&self, coord: Coord, level: u8) -> Option<usize> {
+		let (mut basin, flooded_at) = self.flooded[coord.0 as usize][coord.1 as usize];
+		if flooded_at > level {
+			return None;
+		}
+		while let (Some(death), Some(into)) = (self.basins[basin].death, self.basins[basin].merged_into) {
+			if death > level {
+				break;
+			}
+			basin = into;
+		}
+		return Some(basin);
+	}
+
+	pub fn size_at(&self, basin: usize, level: u8) -> Option<u32> {
+		return self.level(level)?.basins.iter()
+			.find(|(id, _)| *id == basin)
+			.map(|(_, size)| *size);
+	}
+
+	fn fmt_basin(&self, f: &mut fmt::Formatter<'_>, basin: &FloodBasin, depth: usize) -> fmt::Result {
+		write!(f, "{}#{} born at {} {:?}", "  ".repeat(depth), basin.id, basin.birth, basin.low_point)?;
+		match basin.death {
+			Some(death) => writeln!(f, ", merges at {}", death)?,
+			None => writeln!(f)?
+		}
+
+		// Basins that joined this one, the last ones to join first
+		let mut children : Vec<&FloodBasin> = self.basins.iter()
+			.filter(|b| b.merged_into == Some(basin.id))
+			.collect();
+		children.sort_by_key(|b| (std::cmp::Reverse(b.death), b.id));
+		for child in children {
+			self.fmt_basin(f, child, depth + 1)?;
+		}
+		return Ok(());
+	}
+}
+
+impl fmt::Display for MergeTree {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for basin in self.basins.iter().filter(|b| b.death.is_none()) {
+			self.fmt_basin(f, basin, 0)?;
+		}
+		return Ok(());
+	}
+}
+
+pub fn merge_tree(matrix: &Vec<Vec<u8>>, connectivity: Connectivity) -> MergeTree {
+	let height = matrix.len();
+	let width = if height > 0 { matrix[0].len() } else { 0 };
+	let idx = |coord: &Coord| coord.0 as usize * width + coord.1 as usize;
+	let value = |coord: &Coord| matrix[coord.0 as usize][coord.1 as usize];
+
+	let max_level = matrix.iter().flatten().copied().max().unwrap_or(0).max(9);
+	let mut cells : Vec<Coord> = (0..height as u32).flat_map(|row| (0..width as u32).map(move |col| (row, col))).collect();
+	cells.sort_by_key(|c| (value(c), *c));
+
+	let mut components = UnionFind::new(width * height);
+	let mut basins : Vec<FloodBasin> = Vec::new();
+	let mut levels = Vec::new();
+	let mut flooded = vec![vec![(0, 0); width]; height];
+	let mut is_flooded = vec![false; width * height];
+	// Basin of every union-find root of the flooded cells
+	let mut basin_of_root : HashMap<usize, usize> = HashMap::new();
+
+	let mut next = 0;
+	for level in 0..=max_level {
+		let start = next;
+		while next < cells.len() && value(&cells[next]) == level {
+			next += 1;
+		}
+		let new_cells = &cells[start..next];
+
+		for cell in new_cells {
+			is_flooded[idx(cell)] = true;
+			for n in get_neighbourhoods(cell, width as u32, height as u32, connectivity) {
+				if is_flooded[idx(&n)] {
+					components.union(idx(cell), idx(&n));
+				}
+			}
+		}
+
+		// The basins that were there before, grouped by the area they are in now
+		let mut joined : HashMap<usize, Vec<usize>> = HashMap::new();
+		for (root, basin) in &basin_of_root {
+			joined.entry(components.find(*root)).or_default().push(*basin);
+		}
+
+		let mut merges = Vec::new();
+		let mut new_basin_of_root = HashMap::new();
+		for (root, mut old) in joined {
+			old.sort_by_key(|b| (basins[*b].birth, *b));
+			for absorbed in &old[1..] {
+				basins[*absorbed].death = Some(level);
+				basins[*absorbed].merged_into = Some(old[0]);
+				merges.push((*absorbed, old[0]));
+			}
+			new_basin_of_root.insert(root, old[0]);
+		}
+
+		// Cells in sorted order, so the first cell of a new basin is its low point
+		for cell in new_cells {
+			let root = components.find(idx(cell));
+			let basin = *new_basin_of_root.entry(root).or_insert_with(|| {
+				basins.push(FloodBasin { id: basins.len(), birth: level, low_point: *cell, death: None, merged_into: None });
+				basins.len() - 1
+			});
+			flooded[cell.0 as usize][cell.1 as usize] = (basin, level);
+		}
+		basin_of_root = new_basin_of_root;
+
+		let mut sizes : Vec<(usize, u32)> = basin_of_root.iter()
+			.map(|(root, basin)| (*basin, components.size[*root] as u32))
+			.collect();
+		sizes.sort();
+		merges.sort();
+		levels.push(FloodLevel { level, basins: sizes, merges });
+	}
+
+	return MergeTree { basins, levels, flooded };
+}
+
+// Basin map rendering
+// -------------------
+// Every basin gets its own color, darker where the cells are higher, low
+// points are white and walls are gray (lighter when higher). The colors
+// come from a BasinMap, so any options (connectivity, wall height) are
+// drawn as they were computed.
+
+pub type Rgb = (u8, u8, u8);
+
+pub fn basin_colors(matrix: &Vec<Vec<u8>>, basins: &BasinMap) -> Vec<Vec<Rgb>> {
+	let max_height = matrix.iter().flatten().copied().max().unwrap_or(0).max(1) as f64;
+
+	let mut colors : Vec<Vec<Rgb>> = matrix.iter().zip(&basins.labels)
+		.map(|(heights, labels)| heights.iter().zip(labels)
+			.map(|(height, label)| {
+				let shade = *height as f64 / max_height;
+				return match label {
+					Some(basin) => basin_color(*basin, 1.0 - 0.6 * shade),
+					None => {
+						let gray = (64.0 + 128.0 * shade) as u8;
+						(gray, gray, gray)
+					}
+				};
+			})
+			.collect())
+		.collect();
+
+	// Wall plateaus can be low regions too, they stay gray
+	for region in basins.low_regions.iter().filter(|r| r.basin.is_some()) {
+		for cell in &region.cells {
+			colors[cell.0 as usize][cell.1 as usize] = (255, 255, 255);
+		}
+	}
+	return colors;
+}
+
+// Hues spread with the golden ratio, so basins with consecutive labels
+// (usually next to each other) look different
+fn basin_color(basin: usize, brightness: f64) -> Rgb {
+	let hue = (basin as f64 * 0.618_033_988_75).fract() * 6.0;
+	let x = 1.0 - (hue % 2.0 - 1.0).abs();
+	let (r, g, b) = match hue as u32 {
+		0 => (1.0, x, 0.0),
+		1 => (x, 1.0, 0.0),
+		2 => (0.0, 1.0, x),
+		3 => (0.0, x, 1.0),
+		4 => (x, 0.0, 1.0),
+		_ => (1.0, 0.0, x)
+	};
+	let channel = |c: f64| (255.0 * brightness * (0.3 + 0.7 * c)) as u8;
+	return (channel(r), channel(g), channel(b));
+}
+
+// The heights over the colors, with 24 bit ANSI escape codes
+pub fn render_ansi(matrix: &Vec<Vec<u8>>, basins: &BasinMap) -> String {
+	let colors = basin_colors(matrix, basins);
+	let mut result = String::new();
+	for (heights, colors) in matrix.iter().zip(&colors) {
+		for (height, (r, g, b)) in heights.iter().zip(colors) {
+			let symbol = std::char::from_digit(*height as u32, 10).unwrap_or('#');
+			result += &format!("\x1b[48;2;{};{};{}m\x1b[38;2;0;0;0m{}", r, g, b, symbol);
+		}
+		result += "\x1b[0m\n";
+	}
+	return result;
+}
+
+// Binary PPM (P6), one pixel per cell
+// http://netpbm.sourceforge.net/doc/ppm.html
+pub fn render_ppm(matrix: &Vec<Vec<u8>>, basins: &BasinMap) -> Vec<u8> {
+	let colors = basin_colors(matrix, basins);
+	let height = colors.len();
+	let width = if height > 0 { colors[0].len() } else { 0 };
+
+	let mut result = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+	for (r, g, b) in colors.iter().flatten() {
+		result.extend([*r, *g, *b]);
+	}
 	return result;
 }
 
-fn get_neighbourhoods(initial: &Coord, width:u32, height:u32) -> Vec<Coord> {
+pub fn write_ppm(path: &str, matrix: &Vec<Vec<u8>>, basins: &BasinMap) -> Result<(), String> {
+	return std::fs::write(path, render_ppm(matrix, basins)).map_err(|e| format!("{}: {}", path, e));
+}
+
+fn get_neighbourhoods(initial: &Coord, width:u32, height:u32, connectivity: Connectivity) -> Vec<Coord> {
 	let row = initial.0 as i32;
 	let col = initial.1 as i32;
 	let width = width as i32;
 	let height = height as i32;
 
-	let neighbourhoods = [
+	let mut neighbourhoods = vec![
 		(row -1, col),
 		(row,col -1),
 		(row,col +1),
 		(row +1, col),
 	];
+	if connectivity == Connectivity::Eight {
+		neighbourhoods.extend([
+			(row -1, col -1),
+			(row -1, col +1),
+			(row +1, col -1),
+			(row +1, col +1),
+		]);
+	}
 
     let result = neighbourhoods.into_iter()
 	 .filter(|coord| coord.0 >= 0 && coord.0 < height && coord.1 >= 0 && coord.1 < width)
@@ -123,6 +746,7 @@ fn get_neighbourhoods(initial: &Coord, width:u32, height:u32) -> Vec<Coord> {
 	return result;
 }
 
+#[cfg(test)]
 mod tests {
 	use super::*;
 
@@ -147,4 +771,245 @@ mod tests {
 		assert_eq!(result, 1134);
 	}
 
-}
\ No newline at end of file
+	#[test]
+	fn test_day9_label_basins() {
+		let input = parser(INPUT_LITERAL);
+		let basins = label_basins(&input.matrix);
+
+		let mut sizes = basins.sizes.clone();
+		sizes.sort();
+		assert_eq!(sizes, vec![3, 9, 9, 14]);
+		assert_eq!(basins.low_regions.len(), 4);
+		assert!(basins.low_regions.iter().all(|r| r.cells.len() == 1));
+		// Every basin has one low point
+		let mut low_basins : Vec<usize> = basins.low_regions.iter().map(|r| r.basin.unwrap()).collect();
+		low_basins.sort();
+		assert_eq!(low_basins, vec![0, 1, 2, 3]);
+		assert_eq!(basins.labels[0][0], basins.labels[1][0]);
+		assert_eq!(basins.labels[0][2], None);
+	}
+
+	#[test]
+	fn test_day9_plateau_low_regions() {
+		// The strict definition misses the flat bottom of the left basin
+		let input = parser(
+"9999999
+9211399
+9211939
+9333909
+9999999");
+		let basins = label_basins(&input.matrix);
+
+		let mut regions = basins.low_regions.clone();
+		regions.sort_by_key(|r| r.cells.len());
+		assert_eq!(regions.len(), 2);
+		assert_eq!(regions[0].cells, vec![(3, 5)]);
+		assert_eq!(regions[0].height, 0);
+		assert_eq!(regions[1].cells, vec![(1, 2), (1, 3), (2, 2), (2, 3)]);
+		assert_eq!(regions[1].risk, 2);
+		assert_eq!(solve_part1(&input), 3);
+
+		let mut sizes = basins.sizes.clone();
+		sizes.sort();
+		assert_eq!(sizes, vec![2, 10]);
+	}
+
+	#[test]
+	fn test_day9_sonar_options() {
+		// Ridges are 7 or higher, and the basins only touch diagonally
+		let input = parser(
+"1177999
+1177999
+7730899
+9998077
+9999722");
+		let four = HeightmapOptions { wall_height: 7, ..HeightmapOptions::default() };
+		let basins = label_basins_with(&input.matrix, &four);
+		let mut sizes = basins.sizes.clone();
+		sizes.sort();
+		assert_eq!(sizes, vec![1, 2, 2, 4]);
+
+		let eight = HeightmapOptions { connectivity: Connectivity::Eight, ..four };
+		let basins = label_basins_with(&input.matrix, &eight);
+		let mut sizes = basins.sizes.clone();
+		sizes.sort();
+		assert_eq!(sizes, vec![9]);
+		// With diagonals the two zeros are a plateau, and the 2s are not a
+		// low region anymore
+		assert_eq!(basins.low_regions.len(), 2);
+		assert_eq!(label_basins_with(&input.matrix, &four).low_regions.len(), 4);
+
+		fn area_risk(region: &LowRegion) -> u32 {
+			return region.cells.len() as u32 * (10 - region.height as u32);
+		}
+		let custom = HeightmapOptions { risk: area_risk, ..eight };
+		let risk : u32 = label_basins_with(&input.matrix, &custom).low_regions.iter().map(|r| r.risk).sum();
+		assert_eq!(risk, 4 * 9 + 2 * 10);
+	}
+
+	#[test]
+	fn test_day9_wall_plateau() {
+		// A flat map of walls is a low region without a basin, and has no
+		// risk for the puzzle
+		let input = parser("99\n99");
+		let basins = label_basins(&input.matrix);
+		assert_eq!(basins.low_regions.len(), 1);
+		assert_eq!(basins.low_regions[0].basin, None);
+		assert_eq!(solve_part1(&input), 0);
+	}
+
+	#[test]
+	fn test_day9_watershed_matches_basins() {
+		let input = parser(INPUT_LITERAL);
+		let options = HeightmapOptions::default();
+		let basins = label_basins_with(&input.matrix, &options);
+		let watershed = watershed(&input.matrix, &options);
+
+		assert!(watershed.disagreements(&basins).is_empty());
+		assert_eq!(watershed.sizes.iter().sum::<u32>(), 50);
+		// The walls are split between the basins too
+		for (size, region) in watershed.sizes.iter().zip(&watershed.low_regions) {
+			assert!(*size >= basins.sizes[region.basin.unwrap()]);
+		}
+	}
+
+	#[test]
+	fn test_day9_watershed_divides() {
+		let input = parser(
+"0121000
+3333332");
+		let flow = watershed(&input.matrix, &HeightmapOptions::default());
+
+		// Two low regions, the flat bottom on the right counts as one
+		assert_eq!(flow.low_regions.len(), 2);
+		assert_eq!(flow.labels[0], vec![0, 0, 0, 1, 1, 1, 1]);
+		// The 2 drains to the left on the tie, but it is a divide
+		assert!(flow.divides.contains(&(0, 2)));
+		assert_eq!(flow.labels[1], vec![0, 0, 0, 1, 1, 1, 1]);
+		assert_eq!(flow.sizes, vec![6, 8]);
+
+		// Most of the 1s have no lower neighbour, the plateau drains through
+		// its exit next to the 0
+		let input = parser("011112");
+		let flow = watershed(&input.matrix, &HeightmapOptions::default());
+		assert_eq!(flow.labels[0], vec![0; 6]);
+		assert!(flow.divides.is_empty());
+	}
+
+	#[test]
+	fn test_day9_merge_tree() {
+		let input = parser(INPUT_LITERAL);
+		let tree = merge_tree(&input.matrix, Connectivity::Four);
+
+		assert_eq!(tree.levels.len(), 10);
+		assert_eq!(tree.level(0).unwrap().basins.len(), 1);
+		// Just below the walls the basins are the puzzle ones
+		let mut sizes : Vec<u32> = tree.level(8).unwrap().basins.iter().map(|(_, size)| *size).collect();
+		sizes.sort();
+		assert_eq!(sizes, vec![3, 9, 9, 14]);
+		// And at 9 everything is one basin, the oldest one
+		assert_eq!(tree.level(9).unwrap().basins, vec![(0, 50)]);
+		assert_eq!(tree.level(9).unwrap().merges.len(), 3);
+
+		assert_eq!(tree.basin_at((0, 0), 0), None);
+		let top_left = tree.basin_at((0, 1), 1).unwrap();
+		assert_eq!(tree.basins[top_left].birth, 1);
+		assert_eq!(tree.basins[top_left].persistence(), Some(8));
+		assert_eq!(tree.basin_at((0, 0), 2), Some(top_left));
+		assert_eq!(tree.basin_at((0, 0), 9), Some(0));
+		assert_eq!(tree.size_at(top_left, 8), Some(3));
+	}
+
+	#[test]
+	fn test_day9_merge_tree_print() {
+		let input = parser("0510403");
+		let tree = merge_tree(&input.matrix, Connectivity::Four);
+		assert_eq!(tree.to_string(),
+"#0 born at 0 (0, 0)
+  #1 born at 0 (0, 3), merges at 5
+    #2 born at 0 (0, 5), merges at 4
+");
+		assert_eq!(tree.level(4).unwrap().merges, vec![(2, 1)]);
+		assert_eq!(tree.level(4).unwrap().basins, vec![(0, 1), (1, 5)]);
+	}
+
+	#[test]
+	fn test_day9_pgm_ascii() {
+		let pgm = "P2
+# the example, as gray values
+10 5
+# comments are allowed anywhere in the header
+90
+20 10 90 90 90 40 30 20 10 0
+30 90 80 70 80 90 40 90 20 10
+90 80 50 60 70 80 90 80 90 20
+80 70 60 70 80 90 60 70 80 90
+90 80 90 90 90 60 50 60 70 80
+";
+		let input = parse_pgm(pgm.as_bytes(), 10).unwrap();
+		assert_eq!(input.matrix, parser(INPUT_LITERAL).matrix);
+		assert_eq!(solve_part1(&input), 15);
+		assert_eq!(solve_part2(&input), 1134);
+	}
+
+	#[test]
+	fn test_day9_pgm_binary() {
+		let mut pgm = b"P5 3 2 255\n".to_vec();
+		pgm.extend([0, 127, 255, 128, 64, 1]);
+		let input = parse_pgm(&pgm, 4).unwrap();
+		assert_eq!(input.matrix, vec![vec![0, 1, 3], vec![2, 1, 0]]);
+
+		let mut pgm = b"P5 2 1 65535\n".to_vec();
+		pgm.extend([0xff, 0xff, 0x80, 0x00]);
+		let input = parse_pgm(&pgm, 256).unwrap();
+		assert_eq!(input.matrix, vec![vec![255, 128]]);
+
+		assert!(parse_pgm(b"P5 2 2 255\n\x00\x00\x00", 10).is_err());
+		assert!(parse_pgm(b"P6 1 1 255\n\x00", 10).is_err());
+		assert!(parse_pgm(b"P2 1 1 9\n10", 10).is_err());
+		assert!(parse_pgm(b"P2 1 1 9\n5", 1).is_err());
+
+		// Huge headers are rejected without allocating for them
+		assert!(parse_pgm(b"P5 4000000000 4000000000 255\n\x00", 10).is_err());
+		assert!(parse_pgm(b"P5 4000000000 4000000000 65535\n\x00", 10).is_err());
+		assert!(parse_pgm(b"P2 4000000000 4000000000 255\n0 1", 10).is_err());
+	}
+
+	#[test]
+	fn test_day9_render() {
+		let input = parser(INPUT_LITERAL);
+		let basins = label_basins(&input.matrix);
+
+		let colors = basin_colors(&input.matrix, &basins);
+		// Low point, wall, and two cells of different basins
+		assert_eq!(colors[0][1], (255, 255, 255));
+		let (r, g, b) = colors[0][2];
+		assert!(r == g && g == b);
+		assert_ne!(colors[0][0], colors[0][5]);
+
+		let ppm = render_ppm(&input.matrix, &basins);
+		let header = b"P6\n10 5\n255\n";
+		assert_eq!(&ppm[..header.len()], header);
+		assert_eq!(ppm.len(), header.len() + 10 * 5 * 3);
+		assert_eq!(&ppm[header.len() + 3..header.len() + 6], &[255, 255, 255]);
+
+		let ansi = render_ansi(&input.matrix, &basins);
+		assert_eq!(ansi.lines().count(), 5);
+		assert!(ansi.starts_with(&format!("\x1b[48;2;{};{};{}m\x1b[38;2;0;0;0m2", colors[0][0].0, colors[0][0].1, colors[0][0].2)));
+		assert_eq!(ansi.matches("\x1b[48;2;").count(), 50);
+	}
+
+	#[test]
+	fn test_day9_render_wall_plateau() {
+		// The 9s are a low region without a basin, drawn as walls
+		let input = parser("99\n99");
+		let basins = label_basins(&input.matrix);
+		let colors = basin_colors(&input.matrix, &basins);
+		// The lightest gray, they are the highest cells
+		let gray = 192;
+		assert_eq!(colors, vec![vec![(gray, gray, gray); 2]; 2]);
+
+		let ppm = render_ppm(&input.matrix, &basins);
+		assert!(ppm.ends_with(&[gray; 2 * 2 * 3]));
+	}
+}
@@ -1,5 +1,8 @@
 use aoc_runner_derive::aoc_lib;
 
+pub mod bigint;
+pub mod rng;
+
 pub mod day1;
 pub mod day2;
 pub mod day3;
@@ -0,0 +1,66 @@
+// Small seeded pseudo random number generator (SplitMix64).
+// It is not suitable for anything security related, but it is fast,
+// reproducible across platforms and good enough to generate test data and
+// run simulations without pulling an external crate.
+// https://prng.di.unimi.it/splitmix64.c
+
+#[derive(Debug, Clone)]
+pub struct Rng {
+	state: u64
+}
+
+impl Rng {
+	pub fn new(seed: u64) -> Self {
+		return Rng { state: seed };
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+		return z ^ (z >> 31);
+	}
+
+	// Uniform value in [low, high)
+	pub fn gen_range(&mut self, low: u64, high: u64) -> u64 {
+		assert!(low < high, "empty range {}..{}", low, high);
+		return low + self.next_u64() % (high - low);
+	}
+
+	// Uniform value in [0, 1)
+	pub fn gen_f64(&mut self) -> f64 {
+		// 53 bits is the precision of the f64 mantissa
+		return (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64;
+	}
+
+	// Fisher-Yates shuffle
+	pub fn shuffle<T>(&mut self, values: &mut [T]) {
+		for idx in (1..values.len()).rev() {
+			let other = self.gen_range(0, idx as u64 + 1) as usize;
+			values.swap(idx, other);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_rng_reproducible() {
+		let mut a = Rng::new(42);
+		let mut b = Rng::new(42);
+		for _ in 0..100 {
+			assert_eq!(a.next_u64(), b.next_u64());
+		}
+
+		let mut rng = Rng::new(7);
+		for _ in 0..1000 {
+			let v = rng.gen_range(3, 9);
+			assert!((3..9).contains(&v));
+			let f = rng.gen_f64();
+			assert!((0.0..1.0).contains(&f));
+		}
+	}
+}